@@ -0,0 +1,89 @@
+use crate::config::{Config, ThemeName};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(name = "fuu", version, about = "Simple image viewer")]
+// the gallery options mean nothing to the subcommands
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Images, directories or urls to open
+    pub sources: Vec<String>,
+
+    /// Use this config file instead of $XDG_CONFIG_HOME/fuu/config.toml
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Color theme of the interface
+    #[arg(long)]
+    pub theme: Option<ThemeName>,
+
+    /// Width of the generated thumbnails
+    #[arg(long, global = true)]
+    pub thumb_width: Option<u32>,
+
+    /// Number of thumbnails generated at the same time
+    #[arg(short, long, global = true)]
+    pub jobs: Option<usize>,
+
+    /// Where thumbnails and downloaded images are stored
+    #[arg(long, global = true)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Network timeout in seconds
+    #[arg(long, global = true)]
+    pub timeout: Option<u64>,
+
+    /// Start in fullscreen mode
+    #[arg(short, long)]
+    pub fullscreen: bool,
+
+    /// Reopen the last session, or the last session of the given directory
+    #[arg(short, long)]
+    pub resume: bool,
+}
+
+/// Modes that run without opening a window
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Generate the missing thumbnails of images, directories or urls
+    Thumbs {
+        /// Images, directories or urls
+        #[arg(required = true)]
+        sources: Vec<String>,
+
+        /// Only print the images whose thumbnail cannot be made
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Print the format, dimensions, EXIF data and cache status of images as JSON lines
+    Info {
+        /// Images, directories or urls
+        #[arg(required = true)]
+        sources: Vec<String>,
+    },
+}
+
+impl Cli {
+    /// Override the values of `config` given on the command line
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(theme) = self.theme {
+            config.theme = theme;
+        }
+        if let Some(thumb_width) = self.thumb_width {
+            config.layout.thumb_width = thumb_width;
+        }
+        if let Some(jobs) = self.jobs {
+            config.concurrency.thumb_workers = jobs;
+        }
+        if let Some(cache_dir) = &self.cache_dir {
+            config.cache.dir = cache_dir.clone();
+        }
+        if let Some(timeout) = self.timeout {
+            config.network.timeout = timeout;
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::config::ColorConfig;
+use image::codecs::{jpeg::JpegDecoder, png::PngDecoder, tiff::TiffDecoder, webp::WebPDecoder};
+use image::{DynamicImage, ImageDecoder, ImageFormat};
+use qcms::{DataType, Intent, Profile, Transform};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+/// How decoded pixels are converted before they reach the screen
+#[derive(Clone, Debug)]
+pub struct ColorManagement {
+    pub enabled: bool,
+    /// ICC profile of the display, sRGB is assumed when missing
+    pub display_profile: Option<Arc<Vec<u8>>>,
+}
+
+impl Default for ColorManagement {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            display_profile: None,
+        }
+    }
+}
+
+impl ColorManagement {
+    pub fn new(config: &ColorConfig) -> std::io::Result<Self> {
+        let display_profile = match &config.display_profile {
+            Some(path) => Some(Arc::new(load_profile(path)?)),
+            None => None,
+        };
+        Ok(Self {
+            enabled: config.managed,
+            display_profile,
+        })
+    }
+
+    pub fn unmanaged(&self) -> Self {
+        Self {
+            enabled: false,
+            ..self.clone()
+        }
+    }
+
+    fn output_profile(&self) -> Option<Box<Profile>> {
+        match &self.display_profile {
+            Some(icc) => Profile::new_from_slice(icc, false),
+            None => Some(Profile::new_sRGB()),
+        }
+    }
+
+    /// Convert `image` from its embedded `icc` profile (sRGB if none) to the display profile
+    pub fn apply(&self, image: DynamicImage, icc: Option<&[u8]>) -> DynamicImage {
+        if !self.enabled || (icc.is_none() && self.display_profile.is_none()) {
+            return image;
+        }
+        let input = match icc {
+            Some(icc) => Profile::new_from_slice(icc, false),
+            None => Some(Profile::new_sRGB()),
+        };
+        let (Some(input), Some(mut output)) = (input, self.output_profile()) else {
+            return image;
+        };
+        if input.is_sRGB() && self.display_profile.is_none() {
+            return image;
+        }
+        output.precache_output_transform();
+        let Some(transform) =
+            Transform::new(&input, &output, DataType::RGBA8, Intent::Perceptual)
+        else {
+            return image;
+        };
+        let mut rgba_image = image.into_rgba8();
+        transform.apply(&mut rgba_image);
+        DynamicImage::ImageRgba8(rgba_image)
+    }
+}
+
+pub fn load_profile<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<u8>> {
+    let icc = std::fs::read(path)?;
+    if Profile::new_from_slice(&icc, false).is_none() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "invalid ICC profile",
+        ));
+    }
+    Ok(icc)
+}
+
+/// Read the embedded ICC profile of an image supported by the `image` crate
+pub fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let reader = Cursor::new(data);
+    match image::guess_format(data).ok()? {
+        ImageFormat::Png => PngDecoder::new(reader).ok()?.icc_profile(),
+        ImageFormat::Jpeg => JpegDecoder::new(reader).ok()?.icc_profile(),
+        ImageFormat::WebP => WebPDecoder::new(reader).ok()?.icc_profile(),
+        ImageFormat::Tiff => TiffDecoder::new(reader).ok()?.icc_profile(),
+        _ => None,
+    }
+}
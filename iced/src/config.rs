@@ -0,0 +1,362 @@
+use crate::convert::ConvertOptions;
+use crate::gui::style::{COLUMN_SPACING, CONTAINER_PADDING, DEFAULT_IMG_WIDTH, ROW_SPACING};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub theme: ThemeName,
+    /// Vim style navigation with counts, `gg`, `G`, `ctrl+d` and `ctrl+u`
+    pub vim: bool,
+    /// Follow the files added, removed and modified in the opened directories
+    pub watch: bool,
+    pub layout: LayoutConfig,
+    pub concurrency: ConcurrencyConfig,
+    pub cache: CacheConfig,
+    pub network: NetworkConfig,
+    pub color: ColorConfig,
+    pub keys: KeyConfig,
+    pub session: SessionConfig,
+    pub duplicates: DuplicatesConfig,
+    pub crop: CropConfig,
+    /// Defaults of the batch conversion prompt
+    pub convert: ConvertOptions,
+    pub contact_sheet: ContactSheetConfig,
+    /// External programs run on the images, each `[[commands]]` entry has its keys
+    pub commands: Vec<CommandConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: Default::default(),
+            vim: false,
+            watch: true,
+            layout: Default::default(),
+            concurrency: Default::default(),
+            cache: Default::default(),
+            network: Default::default(),
+            color: Default::default(),
+            keys: Default::default(),
+            session: Default::default(),
+            duplicates: Default::default(),
+            crop: Default::default(),
+            convert: Default::default(),
+            contact_sheet: Default::default(),
+            commands: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    /// Width of the generated thumbnails
+    pub thumb_width: u32,
+    /// Number of columns the gallery is reset to
+    pub columns: u32,
+    pub container_padding: u32,
+    pub column_spacing: u32,
+    pub row_spacing: u32,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            thumb_width: DEFAULT_IMG_WIDTH,
+            columns: 5,
+            container_padding: CONTAINER_PADDING,
+            column_spacing: COLUMN_SPACING,
+            row_spacing: ROW_SPACING,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// Number of thumbnails generated at the same time
+    pub thumb_workers: usize,
+    /// Number of previews prefetched on each side of the current image
+    pub prefetch: usize,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            thumb_workers: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            prefetch: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Where thumbnails and downloaded images are stored
+    pub dir: PathBuf,
+    /// Memory kept for decoded previews, in megabytes
+    pub preview_budget: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            dir: dirs::cache_dir().unwrap_or_default().join("fuu"),
+            preview_budget: 512,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Request timeout in seconds
+    pub timeout: u64,
+    pub user_agent: String,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            user_agent: concat!("fuu/", env!("CARGO_PKG_VERSION")).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    pub managed: bool,
+    /// ICC profile of the display, sRGB is assumed when missing
+    pub display_profile: Option<PathBuf>,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        Self {
+            managed: true,
+            display_profile: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// Write the session when fuu is closed
+    pub save: bool,
+    /// Restore the last session of a directory when it is opened again
+    pub auto_resume: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            save: true,
+            auto_resume: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DuplicatesConfig {
+    /// Number of bits two perceptual hashes may differ by for the images to be grouped
+    pub threshold: u32,
+}
+
+impl Default for DuplicatesConfig {
+    fn default() -> Self {
+        Self { threshold: 10 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CropConfig {
+    /// Where crops are written when they shouldn't go next to the original
+    pub clipboard_dir: PathBuf,
+}
+
+impl Default for CropConfig {
+    fn default() -> Self {
+        Self {
+            clipboard_dir: dirs::picture_dir().unwrap_or_default().join("fuu"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContactSheetConfig {
+    pub columns: u32,
+    pub rows: u32,
+    /// Longest edge of the images, in pixels
+    pub cell_size: u32,
+    /// Space around the grid
+    pub margin: u32,
+    /// Space between the cells
+    pub spacing: u32,
+    /// Write the file name and the dimensions under each image
+    pub captions: bool,
+    pub font_size: f32,
+    /// Font file of the captions, a sans-serif system font is used otherwise
+    pub font: Option<PathBuf>,
+    pub dir: PathBuf,
+}
+
+impl Default for ContactSheetConfig {
+    fn default() -> Self {
+        Self {
+            columns: 4,
+            rows: 5,
+            cell_size: 400,
+            margin: 60,
+            spacing: 30,
+            captions: true,
+            font_size: 16.0,
+            font: None,
+            dir: dirs::picture_dir().unwrap_or_default().join("fuu").join("contact-sheets"),
+        }
+    }
+}
+
+/// A program run on images, like `["gimp", "{marked}"]`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandConfig {
+    pub name: String,
+    /// Program and arguments, where `{file}` is the current image, `{marked}` the
+    /// marked images, or the current one when none is, and `{dir}` its directory
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub keys: Keys,
+    /// Load the images given to the program again once it exits
+    #[serde(default)]
+    pub reload: bool,
+}
+
+/// Key bindings by action name, e.g. `next = ["right", "ctrl+n"]`
+pub type KeyConfig = BTreeMap<String, Keys>;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Keys::Many(Vec::new())
+    }
+}
+
+impl Keys {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            Keys::One(key) => std::slice::from_ref(key),
+            Keys::Many(keys) => keys,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "cannot read {}: {}", path.display(), err),
+            Self::Parse(path, err) => write!(f, "invalid config {}:\n{}", path.display(), err),
+            Self::Invalid(path, err) => write!(f, "invalid config {}:\n{}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir().unwrap_or_default().join("fuu")
+}
+
+/// Where fuu remembers things between runs
+pub fn state_dir() -> PathBuf {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .unwrap_or_default()
+        .join("fuu")
+}
+
+/// Where fuu keeps the data created by the user
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir().unwrap_or_default().join("fuu")
+}
+
+impl Config {
+    pub fn default_path() -> PathBuf {
+        config_dir().join("config.toml")
+    }
+
+    /// Load the config file at `path`, or the default one if it exists
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = Self::default_path();
+                if !path.exists() {
+                    return Ok(Self::default());
+                }
+                path
+            }
+        };
+        let content =
+            std::fs::read_to_string(&path).map_err(|err| ConfigError::Io(path.clone(), err))?;
+        let config: Self =
+            toml::from_str(&content).map_err(|err| ConfigError::Parse(path.clone(), err))?;
+        config.validate().map_err(|err| ConfigError::Invalid(path, err))?;
+        Ok(config)
+    }
+
+    /// Check the values that would break the gallery, every problem on its own line
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        if self.layout.thumb_width < 2 {
+            errors.push(format!(
+                "thumb_width must be at least 2, not {}",
+                self.layout.thumb_width
+            ));
+        }
+        if self.layout.columns < 1 {
+            errors.push(String::from("columns must be at least 1"));
+        }
+        if self.concurrency.thumb_workers < 1 {
+            errors.push(String::from("thumb_workers must be at least 1"));
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors.join("\n")),
+        }
+    }
+
+    pub fn preview_budget(&self) -> usize {
+        self.cache.preview_budget * 1024 * 1024
+    }
+}
@@ -0,0 +1,263 @@
+//! Contact sheets, pages with a grid of images and their captions rendered offscreen
+
+use crate::color::ColorManagement;
+use crate::config::ContactSheetConfig;
+use crate::convert::create_output;
+use crate::utils::decode_managed;
+use cosmic_text::{Attrs, Buffer, Family, FontSystem, Metrics, Shaping, SwashCache, Wrap};
+use image::codecs::png::PngEncoder;
+use image::{imageops, DynamicImage, ImageEncoder, Rgba, RgbaImage};
+use std::fmt;
+use std::fs;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const TEXT_COLOR: cosmic_text::Color = cosmic_text::Color::rgb(0x33, 0x33, 0x33);
+/// Height of a caption line relative to the font size
+const LINE_HEIGHT: f32 = 1.3;
+
+/// An image scaled down to fit in a cell of the grid
+pub struct Cell {
+    pub name: String,
+    /// Dimensions of the original image
+    pub dim: (u32, u32),
+    pub thumb: RgbaImage,
+}
+
+impl Cell {
+    /// Decode `data` to sRGB, so every image of the page looks the same in any viewer
+    pub fn new(name: String, data: &[u8], size: u32) -> Result<Self, String> {
+        let image = decode_managed(data, &ColorManagement::default())
+            .map_err(|err| format!("cannot decode {name}: {err}"))?;
+        let dim = (image.width(), image.height());
+        // small images are not blown up
+        let thumb = if dim.0.max(dim.1) > size {
+            image.thumbnail(size, size)
+        } else {
+            image
+        };
+        Ok(Self {
+            name,
+            dim,
+            thumb: thumb.to_rgba8(),
+        })
+    }
+}
+
+/// Fonts and layout of the captions
+struct Captions {
+    font_system: FontSystem,
+    cache: SwashCache,
+    /// Family of the configured font, the default sans-serif one otherwise
+    family: Option<String>,
+    metrics: Metrics,
+}
+
+impl Captions {
+    fn new(config: &ContactSheetConfig) -> Option<Self> {
+        let font = config.font.clone().map(fontdb::Source::File);
+        let font_system = FontSystem::new_with_fonts(font.into_iter());
+        if font_system.db().is_empty() {
+            eprintln!("no font found, the contact sheet has no captions");
+            return None;
+        }
+        let family = config.font.as_ref().and_then(|path| {
+            let face = font_system
+                .db()
+                .faces()
+                .find(|face| matches!(&face.source, fontdb::Source::File(file) if file == path));
+            if face.is_none() {
+                eprintln!("cannot load the font {}", path.display());
+            }
+            face.and_then(|face| face.families.first())
+                .map(|(name, _)| name.clone())
+        });
+        let font_size = config.font_size.max(1.0);
+        Some(Self {
+            font_system,
+            cache: SwashCache::new(),
+            family,
+            metrics: Metrics::new(font_size, (font_size * LINE_HEIGHT).ceil()),
+        })
+    }
+
+    /// Lay out a line of text, its middle cut out when it's wider than `width`
+    fn line(&mut self, text: &str, width: f32) -> (Buffer, f32) {
+        let Self {
+            font_system,
+            family,
+            metrics,
+            ..
+        } = self;
+        let attrs = match family {
+            Some(name) => Attrs::new().family(Family::Name(name)),
+            None => Attrs::new().family(Family::SansSerif),
+        };
+        let mut buffer = Buffer::new(font_system, *metrics);
+        buffer.set_wrap(font_system, Wrap::None);
+        buffer.set_size(font_system, width, metrics.line_height);
+        let chars: Vec<char> = text.chars().collect();
+        for removed in 0..=chars.len() {
+            let shortened;
+            let text = if removed == 0 {
+                text
+            } else {
+                let kept = chars.len() - removed;
+                let head = kept.div_ceil(2);
+                shortened = chars[..head]
+                    .iter()
+                    .chain(['…'].iter())
+                    .chain(chars[chars.len() - (kept - head)..].iter())
+                    .collect::<String>();
+                &shortened
+            };
+            buffer.set_text(font_system, text, attrs, Shaping::Advanced);
+            let line_width = buffer
+                .layout_runs()
+                .map(|run| run.line_w)
+                .fold(0.0, f32::max);
+            if line_width <= width {
+                return (buffer, line_width);
+            }
+        }
+        (buffer, width)
+    }
+
+    /// Blend the glyphs of `buffer` onto the page with their top left corner at `x`, `y`
+    fn draw(&mut self, page: &mut RgbaImage, buffer: &Buffer, x: i32, y: i32) {
+        let (width, height) = (page.width() as i32, page.height() as i32);
+        let draw_pixel = |page: &mut RgbaImage, px: i32, py: i32, color: cosmic_text::Color| {
+            if px < 0 || py < 0 || px >= width || py >= height {
+                return;
+            }
+            let alpha = color.a() as u32;
+            let pixel = page.get_pixel_mut(px as u32, py as u32);
+            for (channel, value) in pixel.0.iter_mut().zip([color.r(), color.g(), color.b()]) {
+                *channel = ((value as u32 * alpha + *channel as u32 * (255 - alpha)) / 255) as u8;
+            }
+        };
+        buffer.draw(
+            &mut self.font_system,
+            &mut self.cache,
+            TEXT_COLOR,
+            |dx, dy, w, h, color| {
+                for py in 0..h as i32 {
+                    for px in 0..w as i32 {
+                        draw_pixel(page, x + dx + px, y + dy + py, color);
+                    }
+                }
+            },
+        );
+    }
+}
+
+/// The pages written for a contact sheet and the images left out of them
+#[derive(Debug, Clone, Default)]
+pub struct Pages {
+    pub written: Vec<PathBuf>,
+    /// Names of the images that could not be read
+    pub skipped: Vec<String>,
+}
+
+/// The layout of the pages, made once for all of them since loading the fonts takes a while
+pub struct ContactSheet {
+    config: ContactSheetConfig,
+    captions: Option<Captions>,
+}
+
+impl ContactSheet {
+    pub fn new(config: ContactSheetConfig) -> Self {
+        let captions = if config.captions {
+            Captions::new(&config)
+        } else {
+            None
+        };
+        Self { config, captions }
+    }
+
+    pub fn cell_size(&self) -> u32 {
+        self.config.cell_size.max(1)
+    }
+
+    pub fn per_page(&self) -> usize {
+        (self.config.columns.max(1) * self.config.rows.max(1)) as usize
+    }
+
+    /// Room under each image for its name and dimensions
+    fn caption_height(&self) -> u32 {
+        match &self.captions {
+            Some(captions) => 2 * captions.metrics.line_height as u32 + self.config.spacing / 2,
+            None => 0,
+        }
+    }
+
+    /// A page with `cells` in rows, the last page only has the rows it fills
+    pub fn render(&mut self, cells: &[Cell]) -> RgbaImage {
+        let ContactSheetConfig {
+            margin, spacing, ..
+        } = self.config;
+        let columns = self.config.columns.max(1);
+        let size = self.cell_size();
+        let rows = (cells.len() as u32).div_ceil(columns).max(1);
+        let cell_height = size + self.caption_height();
+        let mut page = RgbaImage::from_pixel(
+            2 * margin + columns * size + (columns - 1) * spacing,
+            2 * margin + rows * cell_height + (rows - 1) * spacing,
+            BACKGROUND,
+        );
+        for (position, cell) in cells.iter().enumerate() {
+            let (column, row) = (position as u32 % columns, position as u32 / columns);
+            let left = margin + column * (size + spacing);
+            let top = margin + row * (cell_height + spacing);
+            // centered and sitting on the caption
+            let x = left + size.saturating_sub(cell.thumb.width()) / 2;
+            let y = top + size.saturating_sub(cell.thumb.height());
+            imageops::overlay(&mut page, &cell.thumb, x as i64, y as i64);
+
+            let Some(captions) = &mut self.captions else {
+                continue;
+            };
+            let lines = [cell.name.clone(), format!("{}x{}", cell.dim.0, cell.dim.1)];
+            let mut line_top = top + size + spacing / 2;
+            for text in lines {
+                let (buffer, width) = captions.line(&text, size as f32);
+                let x = left as f32 + (size as f32 - width) / 2.0;
+                captions.draw(&mut page, &buffer, x.round() as i32, line_top as i32);
+                line_top += captions.metrics.line_height as u32;
+            }
+        }
+        page
+    }
+
+    /// Render a page into a new PNG file of the output directory
+    pub fn write(&mut self, cells: &[Cell]) -> Result<PathBuf, String> {
+        // the page is opaque, dropping the alpha channel makes a smaller file
+        let page = DynamicImage::ImageRgba8(self.render(cells)).to_rgb8();
+        let dir = &self.config.dir;
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("cannot create {}: {}", dir.display(), err))?;
+        let (path, file) = create_output(dir, "contact-sheet", "png")?;
+        let write_error =
+            |err: &dyn fmt::Display| format!("cannot write {}: {}", path.display(), err);
+        let mut writer = BufWriter::new(file);
+        let result = PngEncoder::new(&mut writer)
+            .write_image(
+                page.as_raw(),
+                page.width(),
+                page.height(),
+                image::ColorType::Rgb8,
+            )
+            .map_err(|err| write_error(&err))
+            .and_then(|_| {
+                writer
+                    .into_inner()
+                    .map(drop)
+                    .map_err(|err| write_error(&err.into_error()))
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&path);
+        }
+        result.map(|_| path)
+    }
+}
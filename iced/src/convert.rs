@@ -0,0 +1,317 @@
+//! Converting images to another format and size, always into new files
+
+use crate::color::icc_profile;
+use crate::exif::{self, jpeg_segment, png_chunks, Orientation, SEGMENT_MAX};
+use crate::utils::decode_image;
+use crate::xmp;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageEncoder};
+use serde::Deserialize;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 6;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Png,
+    #[default]
+    Jpeg,
+    /// Lossless, the encoder of the `image` crate has no lossy mode
+    WebP,
+    /// Only when fuu is built with the `avif` feature
+    Avif,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Avif => "avif",
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Avif => "avif",
+        })
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "jpeg" | "jpg" => Ok(OutputFormat::Jpeg),
+            "webp" => Ok(OutputFormat::WebP),
+            "avif" if cfg!(feature = "avif") => Ok(OutputFormat::Avif),
+            "avif" => Err(String::from("fuu was built without AVIF support")),
+            _ => Err(format!("unknown format `{s}`")),
+        }
+    }
+}
+
+/// How marked images are converted, written in the prompt as
+/// `format=jpeg quality=90 max-edge=2048 metadata=strip dir=/path/to/output`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConvertOptions {
+    pub format: OutputFormat,
+    /// JPEG and AVIF quality from 1 to 100
+    pub quality: u8,
+    /// Longest edge of the output, larger images are scaled down
+    pub max_edge: Option<u32>,
+    /// Copy the EXIF data, the ICC profile and the XMP sidecar
+    pub keep_metadata: bool,
+    pub dir: PathBuf,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Jpeg,
+            quality: 90,
+            max_edge: None,
+            keep_metadata: false,
+            dir: dirs::picture_dir().unwrap_or_default().join("fuu").join("converted"),
+        }
+    }
+}
+
+impl fmt::Display for ConvertOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "format={} quality={} max-edge=", self.format, self.quality)?;
+        match self.max_edge {
+            Some(max_edge) => write!(f, "{max_edge}")?,
+            None => f.write_str("none")?,
+        }
+        let metadata = if self.keep_metadata { "keep" } else { "strip" };
+        write!(f, " metadata={metadata} dir={}", self.dir.display())
+    }
+}
+
+impl FromStr for ConvertOptions {
+    type Err = String;
+
+    /// Options not given keep their default, `dir` comes last since it may contain spaces
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = Self::default();
+        let (settings, dir) = match s.find("dir=") {
+            Some(position) => (&s[..position], Some(s[position + 4..].trim())),
+            None => (s, None),
+        };
+        if let Some(dir) = dir {
+            if dir.is_empty() {
+                return Err(String::from("empty dir"));
+            }
+            options.dir = PathBuf::from(dir);
+        }
+        for setting in settings.split_whitespace() {
+            let Some((key, value)) = setting.split_once('=') else {
+                return Err(format!("expected key=value, found `{setting}`"));
+            };
+            match key {
+                "format" => options.format = value.parse()?,
+                "quality" => {
+                    options.quality = value
+                        .parse()
+                        .ok()
+                        .filter(|quality| (1..=100).contains(quality))
+                        .ok_or_else(|| format!("invalid quality `{value}`"))?;
+                }
+                "max-edge" if value == "none" => options.max_edge = None,
+                "max-edge" => {
+                    let max_edge = value.parse().ok().filter(|&edge: &u32| edge > 0);
+                    options.max_edge =
+                        Some(max_edge.ok_or_else(|| format!("invalid max-edge `{value}`"))?);
+                }
+                "metadata" => {
+                    options.keep_metadata = match value {
+                        "keep" => true,
+                        "strip" => false,
+                        _ => return Err(format!("metadata is keep or strip, not `{value}`")),
+                    };
+                }
+                _ => return Err(format!("unknown option `{key}`")),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Convert the encoded image `data` into a new file named after `stem` in the
+/// output directory, `source` is where the image comes from if it's a file
+pub fn convert(
+    data: &[u8],
+    source: Option<&Path>,
+    stem: &str,
+    options: &ConvertOptions,
+) -> Result<PathBuf, String> {
+    let mut image = decode_image(data).map_err(|err| err.to_string())?;
+    if let Some(max_edge) = options.max_edge {
+        if image.width().max(image.height()) > max_edge {
+            image = image.resize(max_edge, max_edge, FilterType::Lanczos3);
+        }
+    }
+    let mut encoded = encode(&image, options).map_err(|err| err.to_string())?;
+    if options.keep_metadata {
+        // the pixels were already turned to their orientation
+        let exif = exif::exif(data)
+            .and_then(|tiff| exif::with_orientation(&tiff, Orientation::default()));
+        let icc = icc_profile(data);
+        encoded = match options.format {
+            OutputFormat::Jpeg => jpeg_with_metadata(&encoded, exif, icc),
+            OutputFormat::Png => png_with_metadata(&encoded, exif, icc),
+            OutputFormat::WebP | OutputFormat::Avif => encoded,
+        };
+    }
+
+    fs::create_dir_all(&options.dir)
+        .map_err(|err| format!("cannot create {}: {}", options.dir.display(), err))?;
+    let (path, mut file) = create_output(&options.dir, stem, options.format.extension())?;
+    if let Err(err) = file.write_all(&encoded) {
+        drop(file);
+        let _ = fs::remove_file(&path);
+        return Err(format!("cannot write {}: {}", path.display(), err));
+    }
+    if options.keep_metadata {
+        if let Some(sidecar) = source.and_then(xmp::existing_sidecar) {
+            let _ = fs::copy(sidecar, xmp::sidecar_path(&path));
+        }
+    }
+    Ok(path)
+}
+
+fn encode(image: &DynamicImage, options: &ConvertOptions) -> image::ImageResult<Vec<u8>> {
+    let mut encoded = Vec::new();
+    let alpha = image.color().has_alpha();
+    let pixels = if alpha {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+    let (width, height) = (pixels.width(), pixels.height());
+    match options.format {
+        OutputFormat::Png => PngEncoder::new(&mut encoded).write_image(
+            pixels.as_bytes(),
+            width,
+            height,
+            pixels.color(),
+        )?,
+        OutputFormat::Jpeg => JpegEncoder::new_with_quality(&mut encoded, options.quality)
+            .encode_image(&pixels.to_rgb8())?,
+        OutputFormat::WebP => WebPEncoder::new_lossless(&mut encoded).write_image(
+            pixels.as_bytes(),
+            width,
+            height,
+            pixels.color(),
+        )?,
+        #[cfg(feature = "avif")]
+        OutputFormat::Avif => {
+            image::codecs::avif::AvifEncoder::new_with_speed_quality(
+                &mut encoded,
+                AVIF_SPEED,
+                options.quality,
+            )
+            .write_image(pixels.as_bytes(), width, height, pixels.color())?
+        }
+        #[cfg(not(feature = "avif"))]
+        OutputFormat::Avif => {
+            return Err(image::ImageError::Unsupported(
+                image::error::UnsupportedError::from_format_and_kind(
+                    image::ImageFormat::Avif.into(),
+                    image::error::UnsupportedErrorKind::Format(image::ImageFormat::Avif.into()),
+                ),
+            ));
+        }
+    }
+    Ok(encoded)
+}
+
+/// A new file in `dir` like `photo.jpg`, or `photo-2.jpg` when it's taken
+pub fn create_output(dir: &Path, stem: &str, extension: &str) -> Result<(PathBuf, File), String> {
+    let mut number = 1;
+    loop {
+        let path = match number {
+            1 => dir.join(format!("{stem}.{extension}")),
+            _ => dir.join(format!("{stem}-{number}.{extension}")),
+        };
+        // never replace a file, even one created by another conversion
+        match File::options().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => number += 1,
+            Err(err) => return Err(format!("cannot write {}: {}", path.display(), err)),
+        }
+    }
+}
+
+/// Insert the EXIF data and the ICC profile into an encoded JPEG
+pub fn jpeg_with_metadata(jpeg: &[u8], exif: Option<Vec<u8>>, icc: Option<Vec<u8>>) -> Vec<u8> {
+    let mut output = exif
+        .and_then(|exif| exif::jpeg_with_exif(jpeg, &exif))
+        .unwrap_or_else(|| jpeg.to_vec());
+    if let Some(icc) = icc {
+        // profiles larger than a segment are split, each part is numbered
+        let parts: Vec<&[u8]> = icc.chunks(SEGMENT_MAX - ICC_HEADER.len() - 2).collect();
+        if parts.len() <= u8::MAX as usize {
+            let mut segments = Vec::new();
+            for (number, part) in parts.iter().enumerate() {
+                let sequence = [number as u8 + 1, parts.len() as u8];
+                segments.extend(jpeg_segment(0xE2, &[ICC_HEADER, &sequence, part]));
+            }
+            let position = exif::jpeg_metadata_position(&output);
+            output.splice(position..position, segments);
+        }
+    }
+    output
+}
+
+fn png_chunk(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut chunk = (payload.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(payload);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(payload);
+    chunk.extend_from_slice(&crc.sum().to_be_bytes());
+    chunk
+}
+
+/// Insert the EXIF data and the ICC profile after the header of an encoded PNG
+pub fn png_with_metadata(png: &[u8], exif: Option<Vec<u8>>, icc: Option<Vec<u8>>) -> Vec<u8> {
+    let Some(&(_, _, header_end)) = png_chunks(png).first() else {
+        return png.to_vec();
+    };
+    let mut output = png[..header_end].to_vec();
+    if let Some(icc) = icc {
+        let mut encoder = ZlibEncoder::new(b"ICC Profile\0\0".to_vec(), Compression::default());
+        if let Ok(payload) = encoder.write_all(&icc).and_then(|_| encoder.finish()) {
+            output.extend(png_chunk(b"iCCP", &payload));
+        }
+    }
+    if let Some(exif) = exif {
+        output.extend(png_chunk(b"eXIf", &exif));
+    }
+    output.extend_from_slice(&png[header_end..]);
+    output
+}
@@ -0,0 +1,336 @@
+//! Cropping a region of an image into a new file, the source is never written
+
+use crate::color::icc_profile;
+use crate::convert::{jpeg_with_metadata, png_with_metadata};
+use crate::exif::{self, Orientation};
+use crate::utils::decode_image;
+use image::codecs::jpeg::JpegEncoder;
+use image::{ImageFormat, ImageOutputFormat};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+
+const JPEG_QUALITY: u8 = 95;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AspectRatio {
+    #[default]
+    Free,
+    Square,
+    FourThree,
+    ThreeTwo,
+    SixteenNine,
+}
+
+impl AspectRatio {
+    pub fn next(self) -> Self {
+        match self {
+            AspectRatio::Free => AspectRatio::Square,
+            AspectRatio::Square => AspectRatio::FourThree,
+            AspectRatio::FourThree => AspectRatio::ThreeTwo,
+            AspectRatio::ThreeTwo => AspectRatio::SixteenNine,
+            AspectRatio::SixteenNine => AspectRatio::Free,
+        }
+    }
+
+    /// Width over height, `None` when the rectangle is free
+    fn ratio(self) -> Option<f32> {
+        match self {
+            AspectRatio::Free => None,
+            AspectRatio::Square => Some(1.0),
+            AspectRatio::FourThree => Some(4.0 / 3.0),
+            AspectRatio::ThreeTwo => Some(3.0 / 2.0),
+            AspectRatio::SixteenNine => Some(16.0 / 9.0),
+        }
+    }
+}
+
+impl fmt::Display for AspectRatio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AspectRatio::Free => "free",
+            AspectRatio::Square => "1:1",
+            AspectRatio::FourThree => "4:3",
+            AspectRatio::ThreeTwo => "3:2",
+            AspectRatio::SixteenNine => "16:9",
+        })
+    }
+}
+
+/// A region of an image in pixels, always inside of it and at least one pixel wide
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for CropRect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+impl CropRect {
+    /// The largest rectangle with the aspect ratio at the center of the image
+    pub fn centered((width, height): (u32, u32), aspect: AspectRatio) -> Self {
+        Self::around(
+            (width as f32 / 2.0, height as f32 / 2.0),
+            (width as f32, height as f32),
+            aspect,
+            (width, height),
+        )
+    }
+
+    /// The rectangle between a corner where the mouse was pressed and the cursor
+    pub fn from_corners(
+        start: (f32, f32),
+        end: (f32, f32),
+        aspect: AspectRatio,
+        (width, height): (u32, u32),
+    ) -> Self {
+        let clamp = |(x, y): (f32, f32)| (x.clamp(0.0, width as f32), y.clamp(0.0, height as f32));
+        let (start, mut end) = (clamp(start), clamp(end));
+        if let Some(ratio) = aspect.ratio() {
+            // shrinking one side keeps the rectangle inside of the image
+            let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+            let (mut w, mut h) = (dx.abs(), dy.abs());
+            if w > h * ratio {
+                w = h * ratio;
+            } else {
+                h = w / ratio;
+            }
+            end = (start.0 + w.copysign(dx), start.1 + h.copysign(dy));
+        }
+        let (left, top) = (start.0.min(end.0), start.1.min(end.1));
+        let (right, bottom) = (start.0.max(end.0), start.1.max(end.1));
+        Self::clamped(left, top, right - left, bottom - top, (width, height))
+    }
+
+    fn clamped(x: f32, y: f32, w: f32, h: f32, (width, height): (u32, u32)) -> Self {
+        let w = (w.round() as u32).clamp(1, width.max(1));
+        let h = (h.round() as u32).clamp(1, height.max(1));
+        Self {
+            x: (x.round().max(0.0) as u32).min(width.saturating_sub(w)),
+            y: (y.round().max(0.0) as u32).min(height.saturating_sub(h)),
+            width: w,
+            height: h,
+        }
+    }
+
+    /// A rectangle of at most `size`, with the aspect ratio, centered on `center`
+    fn around(
+        center: (f32, f32),
+        (w, h): (f32, f32),
+        aspect: AspectRatio,
+        dim: (u32, u32),
+    ) -> Self {
+        let (w, h) = (w.min(dim.0 as f32), h.min(dim.1 as f32));
+        let (w, h) = match aspect.ratio() {
+            Some(ratio) if w > h * ratio => (h * ratio, h),
+            Some(ratio) => (w, w / ratio),
+            None => (w, h),
+        };
+        Self::clamped(center.0 - w / 2.0, center.1 - h / 2.0, w, h, dim)
+    }
+
+    fn center(&self) -> (f32, f32) {
+        (
+            self.x as f32 + self.width as f32 / 2.0,
+            self.y as f32 + self.height as f32 / 2.0,
+        )
+    }
+
+    pub fn moved(self, dx: i32, dy: i32, dim: (u32, u32)) -> Self {
+        Self::clamped(
+            self.x as f32 + dx as f32,
+            self.y as f32 + dy as f32,
+            self.width as f32,
+            self.height as f32,
+            dim,
+        )
+    }
+
+    /// Grow or shrink each side by `step` pixels around the same center
+    pub fn grown(self, step: i32, aspect: AspectRatio, dim: (u32, u32)) -> Self {
+        // free rectangles keep their own ratio
+        let ratio = aspect.ratio().unwrap_or(self.width as f32 / self.height as f32);
+        let width = (self.width as f32 + 2.0 * step as f32).max(1.0);
+        let height = (width / ratio).max(1.0);
+        Self::around(self.center(), (width, height), aspect, dim)
+    }
+
+    /// The same rectangle shrunk to another aspect ratio
+    pub fn with_aspect(self, aspect: AspectRatio, dim: (u32, u32)) -> Self {
+        Self::around(
+            self.center(),
+            (self.width as f32, self.height as f32),
+            aspect,
+            dim,
+        )
+    }
+}
+
+/// A path in `dir` like `photo-crop.png` that doesn't exist yet
+fn output_path(dir: &Path, stem: &str, extension: &str) -> PathBuf {
+    let mut path = dir.join(format!("{stem}-crop.{extension}"));
+    let mut number = 2;
+    while path.exists() {
+        path = dir.join(format!("{stem}-crop-{number}.{extension}"));
+        number += 1;
+    }
+    path
+}
+
+/// Write `rect` of the encoded image `data` to a new file in `dir`, JPEGs stay
+/// JPEGs and everything else is saved as PNG. The EXIF data and the ICC profile
+/// are copied so the colors stay the same
+pub fn export(data: &[u8], rect: CropRect, dir: &Path, stem: &str) -> Result<PathBuf, String> {
+    let image = decode_image(data).map_err(|err| err.to_string())?;
+    let dim = (image.width(), image.height());
+    let rect = CropRect::clamped(
+        rect.x as f32,
+        rect.y as f32,
+        rect.width as f32,
+        rect.height as f32,
+        dim,
+    );
+    let cropped = image.crop_imm(rect.x, rect.y, rect.width, rect.height);
+    let jpeg = image::guess_format(data).is_ok_and(|format| format == ImageFormat::Jpeg);
+    let mut encoded = Cursor::new(Vec::new());
+    let result = if jpeg {
+        JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY).encode_image(&cropped.to_rgb8())
+    } else {
+        cropped.write_to(&mut encoded, ImageOutputFormat::Png)
+    };
+    result.map_err(|err| err.to_string())?;
+    // the pixels were already turned to their orientation
+    let exif =
+        exif::exif(data).and_then(|tiff| exif::with_orientation(&tiff, Orientation::default()));
+    let icc = icc_profile(data);
+    let encoded = match jpeg {
+        true => jpeg_with_metadata(encoded.get_ref(), exif, icc),
+        false => png_with_metadata(encoded.get_ref(), exif, icc),
+    };
+    fs::create_dir_all(dir).map_err(|err| format!("cannot create {}: {}", dir.display(), err))?;
+    let path = output_path(dir, stem, if jpeg { "jpg" } else { "png" });
+    let write_error = |err: &dyn fmt::Display| format!("cannot write {}: {}", path.display(), err);
+    // never replace a file that appeared in the meantime
+    let mut file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|err| write_error(&err))?;
+    let result = file.write_all(&encoded).map_err(|err| write_error(&err));
+    drop(file);
+    if result.is_err() {
+        let _ = fs::remove_file(&path);
+    }
+    result.map(|_| path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> CropRect {
+        CropRect { x, y, width, height }
+    }
+
+    #[test]
+    fn clamped_keeps_a_rectangle_inside() {
+        assert_eq!(CropRect::clamped(1.0, 2.0, 3.0, 4.0, (10, 10)), rect(1, 2, 3, 4));
+        assert_eq!(CropRect::clamped(1.4, 1.6, 2.5, 2.4, (10, 10)), rect(1, 2, 3, 2));
+    }
+
+    #[test]
+    fn clamped_moves_a_rectangle_back_inside() {
+        assert_eq!(CropRect::clamped(-5.0, -1.0, 3.0, 3.0, (10, 10)), rect(0, 0, 3, 3));
+        assert_eq!(CropRect::clamped(8.0, 9.0, 5.0, 5.0, (10, 10)), rect(5, 5, 5, 5));
+        // the last pixel still fits
+        assert_eq!(CropRect::clamped(9.0, 9.0, 1.0, 1.0, (10, 10)), rect(9, 9, 1, 1));
+    }
+
+    #[test]
+    fn clamped_shrinks_a_rectangle_to_the_image() {
+        assert_eq!(CropRect::clamped(3.0, 3.0, 20.0, 30.0, (10, 8)), rect(0, 0, 10, 8));
+        assert_eq!(CropRect::clamped(0.0, 0.0, 1e12, 1e12, (10, 8)), rect(0, 0, 10, 8));
+    }
+
+    #[test]
+    fn clamped_is_at_least_a_pixel() {
+        assert_eq!(CropRect::clamped(2.0, 2.0, 0.0, -4.0, (10, 10)), rect(2, 2, 1, 1));
+        assert_eq!(CropRect::clamped(2.0, 2.0, f32::NAN, 0.2, (10, 10)), rect(2, 2, 1, 1));
+        // even in an empty image
+        assert_eq!(CropRect::clamped(5.0, 5.0, 5.0, 5.0, (0, 0)), rect(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn centered_fills_the_image() {
+        assert_eq!(CropRect::centered((400, 300), AspectRatio::Free), rect(0, 0, 400, 300));
+        assert_eq!(CropRect::centered((400, 300), AspectRatio::FourThree), rect(0, 0, 400, 300));
+        assert_eq!(CropRect::centered((400, 300), AspectRatio::Square), rect(50, 0, 300, 300));
+        assert_eq!(CropRect::centered((100, 100), AspectRatio::SixteenNine), rect(0, 22, 100, 56));
+    }
+
+    #[test]
+    fn with_aspect_shrinks_around_the_center() {
+        let wide = rect(100, 400, 400, 200);
+        assert_eq!(wide.with_aspect(AspectRatio::Square, (1000, 1000)), rect(200, 400, 200, 200));
+        assert_eq!(wide.with_aspect(AspectRatio::Free, (1000, 1000)), wide);
+        let tall = rect(100, 100, 200, 400);
+        assert_eq!(tall.with_aspect(AspectRatio::ThreeTwo, (1000, 1000)), rect(100, 233, 200, 133));
+    }
+
+    #[test]
+    fn with_aspect_stays_inside_at_the_edges() {
+        let dim = (300, 200);
+        for aspect in [AspectRatio::Square, AspectRatio::SixteenNine, AspectRatio::FourThree] {
+            for corner in [rect(0, 0, 50, 40), rect(250, 160, 50, 40), rect(0, 0, 300, 200)] {
+                let shrunk = corner.with_aspect(aspect, dim);
+                assert!(shrunk.x + shrunk.width <= dim.0, "{corner} {aspect}: {shrunk}");
+                assert!(shrunk.y + shrunk.height <= dim.1, "{corner} {aspect}: {shrunk}");
+                assert!(shrunk.width <= corner.width && shrunk.height <= corner.height);
+            }
+        }
+    }
+
+    #[test]
+    fn export_keeps_the_color_profile() {
+        let dir = std::env::temp_dir().join(format!("fuu-crop-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let image = DynamicImage::ImageRgb8(RgbImage::new(8, 8));
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageOutputFormat::Png).unwrap();
+        let profile = b"not really a profile".to_vec();
+        let png = png_with_metadata(png.get_ref(), None, Some(profile.clone()));
+
+        let path = export(&png, rect(2, 2, 4, 3), &dir, "photo").unwrap();
+        assert_eq!(path, dir.join("photo-crop.png"));
+        let cropped = fs::read(&path).unwrap();
+        assert_eq!(icc_profile(&cropped), Some(profile));
+        assert_eq!(crate::utils::data_dimensions(&cropped).unwrap(), (4, 3));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn export_resets_the_orientation() {
+        let dir = std::env::temp_dir().join(format!("fuu-crop-jpeg-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let image = DynamicImage::ImageRgb8(RgbImage::new(8, 4));
+        let mut jpeg = Cursor::new(Vec::new());
+        image.write_to(&mut jpeg, ImageOutputFormat::Jpeg(90)).unwrap();
+        let turned = exif::orientation_only(Orientation::from_exif(6));
+        let jpeg = exif::jpeg_with_exif(jpeg.get_ref(), &turned).unwrap();
+
+        let path = export(&jpeg, rect(0, 0, 4, 8), &dir, "photo").unwrap();
+        assert_eq!(path, dir.join("photo-crop.jpg"));
+        let cropped = fs::read(&path).unwrap();
+        assert_eq!(exif::orientation(&cropped), Orientation::default());
+        assert_eq!(crate::utils::data_dimensions(&cropped).unwrap(), (4, 8));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+//! Pixel comparison of two images, for reviewing what changed between them
+
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use std::fmt;
+
+/// Relative size difference below which the second image is resized to the first
+const SIZE_TOLERANCE: f32 = 0.05;
+/// Channel difference above which a pixel counts as changed, lower ones are
+/// usually encoder noise
+const CHANGED_THRESHOLD: u8 = 2;
+/// Differences are amplified in the heatmap so small ones stay visible
+const HEATMAP_GAIN: u32 = 4;
+/// Longest edge of the images blended by the onion skin
+const ONION_MAX_EDGE: u32 = 2048;
+const SSIM_WINDOW: u32 = 8;
+
+#[derive(Debug, Clone)]
+pub struct Diff {
+    /// Difference of each pixel, from black to white through red and yellow
+    pub heatmap: RgbaImage,
+    /// Both images at the same, possibly reduced, size for the onion skin
+    pub base: RgbaImage,
+    pub overlay: RgbaImage,
+    /// Peak signal to noise ratio in dB, infinite for identical images
+    pub psnr: f64,
+    /// Mean structural similarity of the luminance, 1 for identical images
+    pub ssim: f64,
+    pub changed: u64,
+    pub total: u64,
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changed = 100.0 * self.changed as f64 / self.total.max(1) as f64;
+        if self.psnr.is_finite() {
+            write!(f, "PSNR {:.2} dB", self.psnr)?;
+        } else {
+            f.write_str("PSNR inf")?;
+        }
+        write!(
+            f,
+            "  SSIM {:.4}  {} changed pixels ({:.2}%)",
+            self.ssim, self.changed, changed
+        )
+    }
+}
+
+/// Compare `second` to `first`, resizing it when their dimensions differ slightly
+pub fn diff(first: &RgbaImage, second: &RgbaImage) -> Result<Diff, String> {
+    let (width, height) = first.dimensions();
+    let (other_width, other_height) = second.dimensions();
+    let differs = |a: u32, b: u32| a.abs_diff(b) as f32 > a.max(1) as f32 * SIZE_TOLERANCE;
+    if differs(width, other_width) || differs(height, other_height) {
+        return Err(format!(
+            "cannot diff {width}x{height} and {other_width}x{other_height} images"
+        ));
+    }
+    let resized;
+    let second = if second.dimensions() == first.dimensions() {
+        second
+    } else {
+        resized = imageops::resize(second, width, height, FilterType::Triangle);
+        &resized
+    };
+
+    let mut heatmap = RgbaImage::new(width, height);
+    let mut squared_error = 0u64;
+    let mut changed = 0;
+    for ((a, b), heat) in first.pixels().zip(second.pixels()).zip(heatmap.pixels_mut()) {
+        let mut largest = 0;
+        for channel in 0..3 {
+            let difference = a[channel].abs_diff(b[channel]);
+            squared_error += difference as u64 * difference as u64;
+            largest = largest.max(difference);
+        }
+        if largest > CHANGED_THRESHOLD {
+            changed += 1;
+        }
+        *heat = heat_color(largest);
+    }
+    let total = width as u64 * height as u64;
+    let mse = squared_error as f64 / (total.max(1) * 3) as f64;
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    };
+
+    let (base, overlay) = if width.max(height) > ONION_MAX_EDGE {
+        (fit(first, ONION_MAX_EDGE), fit(second, ONION_MAX_EDGE))
+    } else {
+        (first.clone(), second.clone())
+    };
+
+    Ok(Diff {
+        heatmap,
+        base,
+        overlay,
+        psnr,
+        ssim: ssim(first, second),
+        changed,
+        total,
+    })
+}
+
+fn fit(image: &RgbaImage, max_edge: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let scale = max_edge as f32 / width.max(height) as f32;
+    let width = ((width as f32 * scale) as u32).max(1);
+    let height = ((height as f32 * scale) as u32).max(1);
+    imageops::resize(image, width, height, FilterType::Triangle)
+}
+
+fn heat_color(difference: u8) -> Rgba<u8> {
+    let t = (difference as u32 * HEATMAP_GAIN).min(255) as f32 / 255.0;
+    let channel = |offset: f32| ((3.0 * t - offset).clamp(0.0, 1.0) * 255.0) as u8;
+    Rgba([channel(0.0), channel(1.0), channel(2.0), 255])
+}
+
+fn luminance(pixel: &Rgba<u8>) -> f64 {
+    0.299 * pixel[0] as f64 + 0.587 * pixel[1] as f64 + 0.114 * pixel[2] as f64
+}
+
+/// Mean SSIM over square windows of the luminance
+fn ssim(first: &RgbaImage, second: &RgbaImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let (width, height) = first.dimensions();
+    let mut total = 0.0;
+    let mut windows = 0;
+    for top in (0..height).step_by(SSIM_WINDOW as usize) {
+        for left in (0..width).step_by(SSIM_WINDOW as usize) {
+            let right = (left + SSIM_WINDOW).min(width);
+            let bottom = (top + SSIM_WINDOW).min(height);
+            let count = ((right - left) * (bottom - top)) as f64;
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in top..bottom {
+                for x in left..right {
+                    let a = luminance(first.get_pixel(x, y));
+                    let b = luminance(second.get_pixel(x, y));
+                    sum_a += a;
+                    sum_b += b;
+                    sum_aa += a * a;
+                    sum_bb += b * b;
+                    sum_ab += a * b;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let variance_a = sum_aa / count - mean_a * mean_a;
+            let variance_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2));
+            windows += 1;
+        }
+    }
+    if windows == 0 {
+        return 1.0;
+    }
+    total / windows as f64
+}
+
+/// `overlay` drawn over `base` with the given opacity, from 0 to 1
+pub fn blend(base: &RgbaImage, overlay: &RgbaImage, opacity: f32) -> RgbaImage {
+    let opacity = opacity.clamp(0.0, 1.0);
+    let mut blended = base.clone();
+    for (pixel, over) in blended.pixels_mut().zip(overlay.pixels()) {
+        for channel in 0..3 {
+            let mixed = pixel[channel] as f32 * (1.0 - opacity) + over[channel] as f32 * opacity;
+            pixel[channel] = mixed.round() as u8;
+        }
+    }
+    blended
+}
@@ -0,0 +1,612 @@
+//! Just enough of EXIF to find, copy and change the orientation of images,
+//! and to read the few fields worth showing about a photo
+
+use image::{DynamicImage, ImageFormat};
+use serde::Serialize;
+
+/// Largest payload of a JPEG segment
+pub const SEGMENT_MAX: usize = 65533;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const ORIENTATION_TAG: u16 = 0x0112;
+const MAKE_TAG: u16 = 0x010F;
+const MODEL_TAG: u16 = 0x0110;
+const DATE_TIME_TAG: u16 = 0x0132;
+/// Offset of the directory with the settings of the camera
+const EXIF_IFD_TAG: u16 = 0x8769;
+const EXPOSURE_TIME_TAG: u16 = 0x829A;
+const F_NUMBER_TAG: u16 = 0x829D;
+const ISO_TAG: u16 = 0x8827;
+const DATE_TIME_ORIGINAL_TAG: u16 = 0x9003;
+const FOCAL_LENGTH_TAG: u16 = 0x920A;
+const LENS_MODEL_TAG: u16 = 0xA434;
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+const IFD_ENTRY: usize = 12;
+
+/// How the stored pixels are turned to be displayed, as the EXIF Orientation
+/// tag describes it: mirrored first, then rotated clockwise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Orientation {
+    /// Clockwise quarter turns
+    turns: u8,
+    mirrored: bool,
+}
+
+/// A change made to the way an image is displayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    RotateRight,
+    RotateLeft,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl Transform {
+    fn orientation(self) -> Orientation {
+        let (turns, mirrored) = match self {
+            Transform::RotateRight => (1, false),
+            Transform::RotateLeft => (3, false),
+            Transform::FlipHorizontal => (0, true),
+            Transform::FlipVertical => (2, true),
+        };
+        Orientation { turns, mirrored }
+    }
+
+    pub fn apply(self, image: DynamicImage) -> DynamicImage {
+        self.orientation().apply(image)
+    }
+}
+
+impl Orientation {
+    /// The orientation of a tag value, unknown values are left as they are
+    pub fn from_exif(value: u16) -> Self {
+        let (turns, mirrored) = match value {
+            2 => (0, true),
+            3 => (2, false),
+            4 => (2, true),
+            5 => (3, true),
+            6 => (1, false),
+            7 => (1, true),
+            8 => (3, false),
+            _ => (0, false),
+        };
+        Self { turns, mirrored }
+    }
+
+    pub fn to_exif(self) -> u16 {
+        match (self.turns % 4, self.mirrored) {
+            (0, false) => 1,
+            (0, true) => 2,
+            (2, false) => 3,
+            (2, true) => 4,
+            (3, true) => 5,
+            (1, false) => 6,
+            (1, true) => 7,
+            _ => 8,
+        }
+    }
+
+    /// Whether the width and the height are swapped
+    pub fn transposes(self) -> bool {
+        self.turns % 2 == 1
+    }
+
+    /// This orientation followed by `transform`
+    pub fn then(self, transform: Transform) -> Self {
+        self.followed_by(transform.orientation())
+    }
+
+    /// This orientation followed by another one
+    pub fn followed_by(self, after: Orientation) -> Self {
+        // a mirror reverses the direction of the turns made before it
+        let turns = if after.mirrored {
+            after.turns + 4 - self.turns
+        } else {
+            after.turns + self.turns
+        };
+        Self {
+            turns: turns % 4,
+            mirrored: self.mirrored ^ after.mirrored,
+        }
+    }
+
+    pub fn apply(self, image: DynamicImage) -> DynamicImage {
+        let image = if self.mirrored { image.fliph() } else { image };
+        match self.turns % 4 {
+            1 => image.rotate90(),
+            2 => image.rotate180(),
+            3 => image.rotate270(),
+            _ => image,
+        }
+    }
+}
+
+/// Segments of a JPEG up to the image data, with their marker
+pub fn jpeg_segments(data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut segments = Vec::new();
+    let mut position = 2;
+    while position + 4 <= data.len() && data[position] == 0xFF {
+        let marker = data[position + 1];
+        // start of scan
+        if marker == 0xDA {
+            break;
+        }
+        let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+        let Some(payload) = data.get(position + 4..position + 2 + length) else {
+            break;
+        };
+        segments.push((marker, payload));
+        position += 2 + length;
+    }
+    segments
+}
+
+pub fn jpeg_segment(marker: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let length: usize = parts.iter().map(|part| part.len()).sum::<usize>() + 2;
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&(length as u16).to_be_bytes());
+    for part in parts {
+        segment.extend_from_slice(part);
+    }
+    segment
+}
+
+/// Chunks of a PNG with their type and the offset where the next one starts
+pub fn png_chunks(data: &[u8]) -> Vec<([u8; 4], &[u8], usize)> {
+    let mut chunks = Vec::new();
+    let mut position = 8;
+    while position + 12 <= data.len() {
+        let length = u32::from_be_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = data[position + 4..position + 8].try_into().unwrap();
+        let Some(payload) = data.get(position + 8..position + 8 + length) else {
+            break;
+        };
+        position += 12 + length;
+        chunks.push((kind, payload, position));
+    }
+    chunks
+}
+
+/// Chunks of a WebP with their FourCC
+fn riff_chunks(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut position = 12;
+    while position + 8 <= data.len() {
+        let kind: [u8; 4] = data[position..position + 4].try_into().unwrap();
+        let length =
+            u32::from_le_bytes(data[position + 4..position + 8].try_into().unwrap()) as usize;
+        let Some(payload) = data.get(position + 8..position + 8 + length) else {
+            break;
+        };
+        chunks.push((kind, payload));
+        // chunks are padded to an even size
+        position += 8 + length + length % 2;
+    }
+    chunks
+}
+
+/// The raw EXIF data of a JPEG, PNG or WebP, starting with the TIFF header
+pub fn exif(data: &[u8]) -> Option<Vec<u8>> {
+    let exif = match image::guess_format(data).ok()? {
+        ImageFormat::Jpeg => jpeg_segments(data)
+            .into_iter()
+            .find_map(|(marker, payload)| match marker {
+                0xE1 => payload.strip_prefix(EXIF_HEADER),
+                _ => None,
+            })?,
+        ImageFormat::Png => png_chunks(data)
+            .into_iter()
+            .find_map(|(kind, payload, _)| (&kind == b"eXIf").then_some(payload))?,
+        ImageFormat::WebP => riff_chunks(data)
+            .into_iter()
+            .find_map(|(kind, payload)| (&kind == b"EXIF").then_some(payload))?,
+        _ => return None,
+    };
+    Some(exif.to_vec())
+}
+
+/// Orientation of an encoded image, from its EXIF data
+pub fn orientation(data: &[u8]) -> Orientation {
+    exif(data)
+        .and_then(|tiff| Tiff::new(&tiff)?.orientation())
+        .map(Orientation::from_exif)
+        .unwrap_or_default()
+}
+
+/// Reads the directories of TIFF data, the first one has the orientation
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+    /// Offset of the first directory
+    ifd: usize,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(..4)? {
+            b"MM\0*" => true,
+            b"II*\0" => false,
+            _ => return None,
+        };
+        let mut tiff = Self { data, big_endian, ifd: 0 };
+        tiff.ifd = tiff.u32(4)? as usize;
+        Some(tiff)
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn write_u16(&self, value: u16) -> [u8; 2] {
+        match self.big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        }
+    }
+
+    fn write_u32(&self, value: u32) -> [u8; 4] {
+        match self.big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        }
+    }
+
+    /// Offsets of the entries of the first directory
+    fn entries(&self) -> Option<impl Iterator<Item = usize>> {
+        self.entries_at(self.ifd)
+    }
+
+    /// Offsets of the entries of the directory at `ifd`
+    fn entries_at(&self, ifd: usize) -> Option<impl Iterator<Item = usize>> {
+        let count = self.u16(ifd)? as usize;
+        let first = ifd + 2;
+        self.data.get(first..first + count * IFD_ENTRY)?;
+        Some((0..count).map(move |entry| first + entry * IFD_ENTRY))
+    }
+
+    /// Entry of `tag` in the directory at `ifd` if it has the type `kind`,
+    /// with the offset of its value, which is inside the entry when it fits
+    fn find(&self, ifd: usize, tag: u16, kind: u16, size: usize) -> Option<(usize, usize)> {
+        let entry = self.entries_at(ifd)?.find(|&entry| self.u16(entry) == Some(tag))?;
+        if self.u16(entry + 2)? != kind {
+            return None;
+        }
+        let count = self.u32(entry + 4)? as usize;
+        let value = match count * size {
+            0..=4 => entry + 8,
+            _ => self.u32(entry + 8)? as usize,
+        };
+        self.data.get(value..value + count * size)?;
+        Some((count, value))
+    }
+
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<String> {
+        let (count, value) = self.find(ifd, tag, ASCII, 1)?;
+        let text = String::from_utf8_lossy(&self.data[value..value + count]);
+        let text = text.trim_end_matches('\0').trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    fn integer(&self, ifd: usize, tag: u16) -> Option<u32> {
+        match self.find(ifd, tag, SHORT, 2) {
+            Some((_, value)) => self.u16(value).map(u32::from),
+            None => self.u32(self.find(ifd, tag, LONG, 4)?.1),
+        }
+    }
+
+    /// Numerator and denominator of an unsigned fraction
+    fn rational(&self, ifd: usize, tag: u16) -> Option<(u32, u32)> {
+        let (_, value) = self.find(ifd, tag, RATIONAL, 8)?;
+        let fraction = (self.u32(value)?, self.u32(value + 4)?);
+        (fraction.1 != 0).then_some(fraction)
+    }
+
+    fn orientation(&self) -> Option<u16> {
+        let entry = self.entries()?.find(|&entry| self.u16(entry) == Some(ORIENTATION_TAG))?;
+        self.u16(entry + 8)
+    }
+
+    /// A copy with another orientation, the first directory is moved to the end
+    /// when it has no orientation entry yet
+    fn with_orientation(&self, value: u16) -> Option<Vec<u8>> {
+        let mut data = self.data.to_vec();
+        let mut entries: Vec<usize> = self.entries()?.collect();
+        if let Some(&entry) = entries.iter().find(|&&entry| self.u16(entry) == Some(ORIENTATION_TAG)) {
+            data[entry + 2..entry + 4].copy_from_slice(&self.write_u16(SHORT));
+            data[entry + 4..entry + 8].copy_from_slice(&self.write_u32(1));
+            data[entry + 8..entry + 12].copy_from_slice(&[0; 4]);
+            data[entry + 8..entry + 10].copy_from_slice(&self.write_u16(value));
+            return Some(data);
+        }
+        // the other entries point to their values with absolute offsets so
+        // appending keeps them valid
+        let next_ifd = self.u32(self.ifd + 2 + entries.len() * IFD_ENTRY)?;
+        let mut orientation = Vec::with_capacity(IFD_ENTRY);
+        orientation.extend(self.write_u16(ORIENTATION_TAG));
+        orientation.extend(self.write_u16(SHORT));
+        orientation.extend(self.write_u32(1));
+        orientation.extend(self.write_u16(value));
+        orientation.extend([0; 2]);
+        // entries are sorted by tag
+        entries.sort_by_key(|&entry| self.u16(entry));
+        let position = entries
+            .iter()
+            .position(|&entry| self.u16(entry).is_some_and(|tag| tag > ORIENTATION_TAG))
+            .unwrap_or(entries.len());
+        // directories start on a word boundary
+        if data.len() % 2 == 1 {
+            data.push(0);
+        }
+        let ifd = data.len();
+        data.extend(self.write_u16(entries.len() as u16 + 1));
+        for (number, entry) in entries.iter().enumerate() {
+            if number == position {
+                data.extend(&orientation);
+            }
+            data.extend_from_slice(&self.data[*entry..*entry + IFD_ENTRY]);
+        }
+        if position == entries.len() {
+            data.extend(&orientation);
+        }
+        data.extend(self.write_u32(next_ifd));
+        data[4..8].copy_from_slice(&self.write_u32(ifd as u32));
+        Some(data)
+    }
+}
+
+/// The EXIF fields worth showing about a photo
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Highlights {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    /// When the photo was taken, as the camera writes it
+    pub date: Option<String>,
+    /// Exposure time in seconds, like `1/250` or `2.5`
+    pub exposure: Option<String>,
+    pub f_number: Option<f64>,
+    pub iso: Option<u32>,
+    /// Focal length in millimeters
+    pub focal_length: Option<f64>,
+    pub orientation: Option<u16>,
+}
+
+/// The highlights of the EXIF data of an encoded image, if it has any
+pub fn highlights(data: &[u8]) -> Option<Highlights> {
+    let tiff = exif(data)?;
+    let tiff = Tiff::new(&tiff)?;
+    let camera = tiff.integer(tiff.ifd, EXIF_IFD_TAG).map(|ifd| ifd as usize);
+    let ascii = |tag| camera.and_then(|ifd| tiff.ascii(ifd, tag));
+    let rational = |tag| camera.and_then(|ifd| tiff.rational(ifd, tag));
+    let decimal = |(numerator, denominator): (u32, u32)| {
+        (numerator as f64 / denominator as f64 * 10.0).round() / 10.0
+    };
+    Some(Highlights {
+        make: tiff.ascii(tiff.ifd, MAKE_TAG),
+        model: tiff.ascii(tiff.ifd, MODEL_TAG),
+        lens: ascii(LENS_MODEL_TAG),
+        date: ascii(DATE_TIME_ORIGINAL_TAG).or_else(|| tiff.ascii(tiff.ifd, DATE_TIME_TAG)),
+        exposure: rational(EXPOSURE_TIME_TAG).map(|(numerator, denominator)| {
+            if numerator != 0 && numerator < denominator {
+                format!("1/{}", (denominator as f64 / numerator as f64).round())
+            } else {
+                decimal((numerator, denominator)).to_string()
+            }
+        }),
+        f_number: rational(F_NUMBER_TAG).map(decimal),
+        iso: camera.and_then(|ifd| tiff.integer(ifd, ISO_TAG)),
+        focal_length: rational(FOCAL_LENGTH_TAG).map(decimal),
+        orientation: tiff.orientation(),
+    })
+}
+
+/// TIFF data with another orientation, or `None` if it cannot be read
+pub fn with_orientation(tiff: &[u8], orientation: Orientation) -> Option<Vec<u8>> {
+    Tiff::new(tiff)?.with_orientation(orientation.to_exif())
+}
+
+/// The smallest EXIF data there is, with only an orientation
+pub fn orientation_only(orientation: Orientation) -> Vec<u8> {
+    let mut tiff = b"MM\0*".to_vec();
+    tiff.extend(8u32.to_be_bytes());
+    tiff.extend(1u16.to_be_bytes());
+    tiff.extend(ORIENTATION_TAG.to_be_bytes());
+    tiff.extend(SHORT.to_be_bytes());
+    tiff.extend(1u32.to_be_bytes());
+    tiff.extend(orientation.to_exif().to_be_bytes());
+    tiff.extend([0; 2]);
+    // no other directory
+    tiff.extend(0u32.to_be_bytes());
+    tiff
+}
+
+/// Where EXIF and other metadata segments go, after the JFIF header if there is one
+pub fn jpeg_metadata_position(jpeg: &[u8]) -> usize {
+    match jpeg_segments(jpeg).first() {
+        Some((0xE0, payload)) => 2 + 4 + payload.len(),
+        _ => 2,
+    }
+}
+
+/// A JPEG whose EXIF data is replaced by `tiff`, or given it if it had none,
+/// the image data is copied as it is
+pub fn jpeg_with_exif(jpeg: &[u8], tiff: &[u8]) -> Option<Vec<u8>> {
+    if tiff.len() + EXIF_HEADER.len() > SEGMENT_MAX {
+        return None;
+    }
+    let segment = jpeg_segment(0xE1, &[EXIF_HEADER, tiff]);
+    let mut position = 2;
+    for (marker, payload) in jpeg_segments(jpeg) {
+        let end = position + 4 + payload.len();
+        if marker == 0xE1 && payload.starts_with(EXIF_HEADER) {
+            let mut output = jpeg[..position].to_vec();
+            output.extend(segment);
+            output.extend_from_slice(&jpeg[end..]);
+            return Some(output);
+        }
+        position = end;
+    }
+    let position = jpeg_metadata_position(jpeg);
+    let mut output = jpeg[..position].to_vec();
+    output.extend(segment);
+    output.extend_from_slice(&jpeg[position..]);
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    const TRANSFORMS: [Transform; 4] = [
+        Transform::RotateRight,
+        Transform::RotateLeft,
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+    ];
+
+    /// TIFF data with a make and a date but no orientation, the make sorts
+    /// before the orientation and the date after it
+    fn tiff_without_orientation(big_endian: bool) -> Vec<u8> {
+        let u16 = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let u32 = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let mut tiff = if big_endian { b"MM\0*".to_vec() } else { b"II*\0".to_vec() };
+        tiff.extend(u32(8));
+        tiff.extend(u16(2));
+        // the values follow the directory, which ends at 8 + 2 + 2 * 12 + 4
+        for (tag, count, offset) in [(MAKE_TAG, 6, 38), (DATE_TIME_TAG, 20, 44)] {
+            tiff.extend(u16(tag));
+            tiff.extend(u16(ASCII));
+            tiff.extend(u32(count));
+            tiff.extend(u32(offset));
+        }
+        tiff.extend(u32(0));
+        tiff.extend(b"Canon\0");
+        tiff.extend(b"2024:05:01 10:20:30\0");
+        tiff
+    }
+
+    fn tags(tiff: &Tiff) -> Vec<u16> {
+        tiff.entries().unwrap().filter_map(|entry| tiff.u16(entry)).collect()
+    }
+
+    #[test]
+    fn exif_values_round_trip() {
+        for value in 1..=8 {
+            assert_eq!(Orientation::from_exif(value).to_exif(), value);
+        }
+    }
+
+    #[test]
+    fn unknown_exif_values_are_upright() {
+        assert_eq!(Orientation::from_exif(0), Orientation::default());
+        assert_eq!(Orientation::from_exif(9), Orientation::default());
+        assert_eq!(Orientation::default().to_exif(), 1);
+    }
+
+    #[test]
+    fn then_gives_the_exif_orientation() {
+        let upright = Orientation::default();
+        assert_eq!(upright.then(Transform::RotateRight).to_exif(), 6);
+        assert_eq!(upright.then(Transform::RotateLeft).to_exif(), 8);
+        assert_eq!(upright.then(Transform::FlipHorizontal).to_exif(), 2);
+        assert_eq!(upright.then(Transform::FlipVertical).to_exif(), 4);
+        // mirrored after the turn, so the turn goes the other way
+        assert_eq!(Orientation::from_exif(6).then(Transform::FlipHorizontal).to_exif(), 5);
+        assert_eq!(Orientation::from_exif(2).then(Transform::RotateRight).to_exif(), 7);
+    }
+
+    #[test]
+    fn then_undoes_itself() {
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value);
+            let turned = (0..4).fold(orientation, |turned, _| turned.then(Transform::RotateRight));
+            assert_eq!(turned, orientation);
+            let back = orientation.then(Transform::RotateRight).then(Transform::RotateLeft);
+            assert_eq!(back, orientation);
+            for flip in [Transform::FlipHorizontal, Transform::FlipVertical] {
+                assert_eq!(orientation.then(flip).then(flip), orientation);
+            }
+        }
+    }
+
+    #[test]
+    fn then_matches_the_pixels() {
+        let image = RgbImage::from_fn(3, 2, |x, y| image::Rgb([x as u8, y as u8, 0]));
+        let image = DynamicImage::ImageRgb8(image);
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value);
+            for transform in TRANSFORMS {
+                let expected = transform.apply(orientation.apply(image.clone()));
+                let combined = orientation.then(transform).apply(image.clone());
+                assert_eq!(combined.to_rgb8(), expected.to_rgb8(), "{value} then {transform:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn followed_by_combines_queued_transforms() {
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value);
+            for first in TRANSFORMS {
+                for second in TRANSFORMS {
+                    let queued = Orientation::default().then(first).then(second);
+                    assert_eq!(
+                        orientation.followed_by(queued),
+                        orientation.then(first).then(second)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn with_orientation_replaces_the_entry() {
+        let tiff = orientation_only(Orientation::from_exif(6));
+        let changed = with_orientation(&tiff, Orientation::from_exif(3)).unwrap();
+        assert_eq!(changed.len(), tiff.len());
+        assert_eq!(Tiff::new(&changed).unwrap().orientation(), Some(3));
+    }
+
+    #[test]
+    fn with_orientation_adds_a_missing_entry() {
+        for big_endian in [true, false] {
+            let data = tiff_without_orientation(big_endian);
+            let tiff = Tiff::new(&data).unwrap();
+            assert_eq!(tiff.orientation(), None);
+
+            let changed = tiff.with_orientation(8).unwrap();
+            // the old directory and the values stay where they were
+            assert_eq!(changed[8..data.len()], data[8..]);
+            let tiff = Tiff::new(&changed).unwrap();
+            assert_eq!(tiff.ifd, data.len());
+            assert_eq!(tiff.orientation(), Some(8));
+            assert_eq!(tags(&tiff), [MAKE_TAG, ORIENTATION_TAG, DATE_TIME_TAG]);
+            assert_eq!(tiff.ascii(tiff.ifd, MAKE_TAG).as_deref(), Some("Canon"));
+            assert_eq!(
+                tiff.ascii(tiff.ifd, DATE_TIME_TAG).as_deref(),
+                Some("2024:05:01 10:20:30")
+            );
+            assert_eq!(tiff.u32(tiff.ifd + 2 + 3 * IFD_ENTRY), Some(0));
+        }
+    }
+
+    #[test]
+    fn with_orientation_rejects_other_data() {
+        assert_eq!(with_orientation(b"not a tiff", Orientation::default()), None);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::config::state_dir;
+use iced::window;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Size and position of the window, restored on the next run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub width: u32,
+    pub height: u32,
+    pub position: Option<(i32, i32)>,
+}
+
+impl Default for WindowGeometry {
+    fn default() -> Self {
+        let (width, height) = window::Settings::default().size;
+        Self {
+            width,
+            height,
+            position: None,
+        }
+    }
+}
+
+impl WindowGeometry {
+    fn path() -> PathBuf {
+        state_dir().join("window.toml")
+    }
+
+    pub fn load() -> Option<Self> {
+        let content = std::fs::read_to_string(Self::path()).ok()?;
+        toml::from_str(&content).ok()
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let content = toml::to_string(self).map_err(std::io::Error::other)?;
+        std::fs::create_dir_all(state_dir())?;
+        std::fs::write(Self::path(), content)
+    }
+
+    pub fn settings(&self) -> window::Settings {
+        let position = match self.position {
+            Some((x, y)) => window::Position::Specific(x, y),
+            None => window::Position::Default,
+        };
+        window::Settings {
+            size: (self.width, self.height),
+            position,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::gui::types::{ImageCard, ImageState, Preview};
+use crate::gui::widgets::synced_viewer::{SyncedViewer, ViewTransform};
+use crate::gui::Message;
+use iced::widget::{column, container, row, slider, text};
+use iced::{Alignment, Element, Length};
+
+fn pane(image_card: &ImageCard, transform: ViewTransform) -> Element<Message> {
+    let image = match &image_card.preview_state {
+        ImageState::Loaded(preview) => SyncedViewer::new(
+            preview.handle.clone(),
+            (preview.width, preview.height),
+            transform,
+            Message::CompareTransform,
+        )
+        .into(),
+        ImageState::Loading => Element::from(
+            container(text("loading ..."))
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .center_x()
+                .center_y(),
+        ),
+        ImageState::Error => Element::from(
+            container(text("error"))
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .center_x()
+                .center_y(),
+        ),
+    };
+    let name = image_card
+        .preview
+        .as_path()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let caption = match image_card.source_dim {
+        Some((width, height)) => format!("{name}  {width}x{height}"),
+        None => name,
+    };
+    column![image, container(text(caption).size(14)).width(Length::Fill).center_x()]
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .spacing(4)
+        .into()
+}
+
+/// Images side by side, zoomed and panned together
+pub fn compare<'a>(
+    image_cards: Vec<&'a ImageCard>,
+    transform: ViewTransform,
+    dim: (u32, u32),
+) -> Element<'a, Message> {
+    let panes = image_cards
+        .into_iter()
+        .map(|image_card| pane(image_card, transform))
+        .collect();
+    container(row(panes).spacing(4).padding(4))
+        .width(dim.0 as u16)
+        .height(dim.1 as u16)
+        .into()
+}
+
+/// The difference of two images, or the second one blended over the first
+/// with a slider for its opacity
+pub fn diff_view<'a>(
+    image: Option<&'a Preview>,
+    status: String,
+    transform: ViewTransform,
+    opacity: Option<f32>,
+    dim: (u32, u32),
+) -> Element<'a, Message> {
+    // the status takes the place of the image until there is one
+    let (image, status) = match image {
+        Some(preview) => (
+            SyncedViewer::new(
+                preview.handle.clone(),
+                (preview.width, preview.height),
+                transform,
+                Message::CompareTransform,
+            )
+            .into(),
+            status,
+        ),
+        None => (
+            Element::from(
+                container(text(status))
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .center_x()
+                    .center_y(),
+            ),
+            String::new(),
+        ),
+    };
+    let mut footer = row![text(status).size(14)].spacing(8).align_items(Alignment::Center);
+    if let Some(opacity) = opacity {
+        footer = footer.push(
+            slider(0.0..=1.0, opacity, Message::OnionOpacity)
+                .step(0.01)
+                .width(200),
+        );
+    }
+    container(
+        column![image, container(footer).width(Length::Fill).center_x()]
+            .spacing(4)
+            .padding(4),
+    )
+    .width(dim.0 as u16)
+    .height(dim.1 as u16)
+    .into()
+}
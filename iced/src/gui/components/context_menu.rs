@@ -0,0 +1,29 @@
+use crate::gui::style::ModalStyle;
+use crate::gui::types::CardAction;
+use crate::gui::Message;
+use iced::widget::{button, column, container, text};
+use iced::{theme, Element, Length};
+
+fn menu_entry<'a>(label: &str, action: Option<CardAction>, position: usize) -> Element<'a, Message> {
+    button(text(label))
+        .width(Length::Fill)
+        .style(theme::Button::Text)
+        .on_press_maybe(action.map(|action| Message::CardAction(action, position)))
+        .into()
+}
+
+pub fn context_menu<'a>(position: usize, marked: bool, is_file: bool) -> Element<'a, Message> {
+    let mark_label = if marked { "Unmark" } else { "Mark" };
+    let open_folder = is_file.then_some(CardAction::OpenFolder);
+    container(column![
+        menu_entry("Open", Some(CardAction::Open), position),
+        menu_entry(mark_label, Some(CardAction::Mark), position),
+        menu_entry("Copy path", Some(CardAction::CopyPath), position),
+        menu_entry("Open containing folder", open_folder, position),
+        menu_entry("Reload thumbnail", Some(CardAction::ReloadThumbnail), position),
+    ])
+    .width(220)
+    .padding(5)
+    .style(theme::Container::Custom(Box::new(ModalStyle)))
+    .into()
+}
@@ -1,16 +1,25 @@
 use crate::gui::components::icons::{arrow_left_icon, arrow_right_icon};
-use crate::gui::types::{ImageCard, ImageState};
+use crate::crop::{AspectRatio, CropRect};
+use crate::gui::types::{HistogramPreview, ImageCard, ImageState, Preview};
+use crate::gui::style::PanelStyle;
+use crate::gui::widgets::corner_overlay::CornerOverlay;
+use crate::gui::widgets::crop_area::CropArea;
+use crate::gui::keymap::Action;
 use crate::gui::Message;
+use crate::histogram::{Channel, GRAPH_HEIGHT, GRAPH_WIDTH};
 
-use iced::keyboard::KeyCode;
-use iced::widget::image::{viewer, Handle};
-use iced::widget::{button, container, row, text};
-use iced::{alignment, theme, Element, Length};
+use iced::widget::image::{viewer, Image};
+use iced::widget::{button, column, container, row, text};
+use iced::{alignment, theme, Color, Element, Length};
 
-pub fn image_preview(image_card: &ImageCard, dim: (u32, u32)) -> Element<Message> {
+pub fn image_preview<'a>(
+    image_card: &'a ImageCard,
+    dim: (u32, u32),
+    panel: Option<Element<'a, Message>>,
+) -> Element<'a, Message> {
     let image = match &image_card.preview_state {
-        ImageState::Loaded(bytes) => Element::new(
-            viewer(Handle::from_memory(bytes.clone()))
+        ImageState::Loaded(preview) => Element::new(
+            viewer(preview.handle.clone())
                 .width(Length::Fill)
                 .height(Length::Fill)
         ),
@@ -29,14 +38,19 @@ pub fn image_preview(image_card: &ImageCard, dim: (u32, u32)) -> Element<Message
                 .center_y()
         ),
     };
+    // drawn over the image so showing it does not move the image around
+    let image = match panel {
+        Some(panel) => CornerOverlay::new(image, panel).into(),
+        None => image,
+    };
     let content = row![
         button(arrow_left_icon())
-            .on_press(Message::KeyPress(KeyCode::Left))
+            .on_press(Message::Action(Action::Previous))
             .height(dim.1 as u16)
             .style(theme::Button::Text),
         image,
         button(arrow_right_icon())
-            .on_press(Message::KeyPress(KeyCode::Right))
+            .on_press(Message::Action(Action::Next))
             .height(dim.1 as u16)
             .style(theme::Button::Text),
     ]
@@ -49,3 +63,55 @@ pub fn image_preview(image_card: &ImageCard, dim: (u32, u32)) -> Element<Message
         .center_y()
         .into()
 }
+
+/// Histograms and channel statistics shown over the corner of the preview
+pub fn histogram_panel(histogram: Option<&HistogramPreview>) -> Element<Message> {
+    let Some(histogram) = histogram else {
+        return container(text("computing the histogram ...").size(14))
+            .width(GRAPH_WIDTH as u16 + 16)
+            .padding(8)
+            .style(theme::Container::Custom(Box::new(PanelStyle)))
+            .into();
+    };
+    let graph = Image::new(histogram.graph.handle.clone())
+        .width(GRAPH_WIDTH as u16)
+        .height(GRAPH_HEIGHT as u16);
+    let mut content = column![graph].spacing(4);
+    for channel in Channel::ALL {
+        if let Some(stats) = histogram.histogram.stats(channel) {
+            content = content.push(text(format!("{channel}  {stats}")).size(14));
+        }
+    }
+    for warning in histogram.histogram.clipping() {
+        content = content.push(
+            text(warning)
+                .size(14)
+                .style(theme::Text::Color(Color::from_rgb(0.9, 0.3, 0.2))),
+        );
+    }
+    container(content)
+        .padding(8)
+        .style(theme::Container::Custom(Box::new(PanelStyle)))
+        .into()
+}
+
+/// The whole image with the crop rectangle drawn over it
+pub fn crop_preview(
+    preview: &Preview,
+    rect: CropRect,
+    aspect: AspectRatio,
+    dim: (u32, u32),
+) -> Element<Message> {
+    let area = CropArea::new(
+        preview.handle.clone(),
+        (preview.width, preview.height),
+        rect,
+        aspect,
+        Message::CropChanged,
+    );
+    container(area)
+        .width(dim.0 as u16)
+        .height(dim.1 as u16)
+        .padding(4)
+        .into()
+}
@@ -1,8 +1,14 @@
+mod compare;
+mod context_menu;
 mod errors;
 pub mod icons;
 mod image_preview;
+mod status_bar;
 mod welcome_page;
 
+pub use compare::*;
+pub use context_menu::*;
 pub use errors::*;
 pub use image_preview::*;
+pub use status_bar::*;
 pub use welcome_page::*;
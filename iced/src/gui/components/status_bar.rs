@@ -0,0 +1,41 @@
+use crate::gui::style::STATUS_BAR_HEIGHT;
+use crate::gui::types::Prompt;
+use crate::gui::Message;
+use iced::widget::{container, horizontal_space, row, text, text_input};
+use iced::{alignment, Element, Length};
+use once_cell::sync::Lazy;
+
+pub static PROMPT_ID: Lazy<text_input::Id> = Lazy::new(text_input::Id::unique);
+
+pub fn status_bar<'a>(left: String, right: String) -> Element<'a, Message> {
+    container(
+        row![text(left).size(14), horizontal_space(Length::Fill), text(right).size(14)]
+            .padding([0, 10]),
+    )
+    .width(Length::Fill)
+    .height(STATUS_BAR_HEIGHT as u16)
+    .center_y()
+    .into()
+}
+
+/// Text input replacing the status bar, submitted with enter
+pub fn prompt_bar(prompt: &Prompt) -> Element<Message> {
+    container(
+        row![
+            text(prompt.label()).size(14),
+            text_input("", &prompt.value)
+                .id(PROMPT_ID.clone())
+                .on_input(Message::PromptChanged)
+                .on_submit(Message::PromptSubmitted)
+                .size(14)
+                .padding(2)
+        ]
+        .spacing(6)
+        .align_items(alignment::Alignment::Center)
+        .padding([0, 10]),
+    )
+    .width(Length::Fill)
+    .height(STATUS_BAR_HEIGHT as u16)
+    .center_y()
+    .into()
+}
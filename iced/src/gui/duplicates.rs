@@ -0,0 +1,76 @@
+use std::cmp::Reverse;
+use std::path::PathBuf;
+
+/// An image with a perceptual hash, and what tells the largest of a group
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub index: usize,
+    pub dhash: u64,
+    /// Width times height of the source image, 0 when unknown
+    pub pixels: u64,
+    /// Local file, whose size breaks ties between images of the same dimensions
+    pub path: Option<PathBuf>,
+}
+
+impl Candidate {
+    fn size(&self) -> (u64, u64) {
+        let bytes = self
+            .path
+            .as_ref()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map_or(0, |metadata| metadata.len());
+        (self.pixels, bytes)
+    }
+}
+
+/// Group the candidates that look alike, the largest image first in each group
+pub fn find(candidates: &[Candidate], threshold: u32) -> Vec<Vec<usize>> {
+    let hashes: Vec<(usize, u64)> = candidates
+        .iter()
+        .enumerate()
+        .map(|(position, candidate)| (position, candidate.dhash))
+        .collect();
+    group(&hashes, threshold)
+        .into_iter()
+        .map(|mut group| {
+            group.sort_by_cached_key(|&position| Reverse(candidates[position].size()));
+            group
+                .into_iter()
+                .map(|position| candidates[position].index)
+                .collect()
+        })
+        .collect()
+}
+
+/// Group the images whose perceptual hashes are at most `threshold` bits
+/// apart, images without a similar one are left out. Groups keep the order
+/// of `hashes`
+pub fn group(hashes: &[(usize, u64)], threshold: u32) -> Vec<Vec<usize>> {
+    let mut parents: Vec<usize> = (0..hashes.len()).collect();
+    fn root(parents: &mut [usize], mut node: usize) -> usize {
+        while parents[node] != node {
+            parents[node] = parents[parents[node]];
+            node = parents[node];
+        }
+        node
+    }
+    for (i, (_, hash)) in hashes.iter().enumerate() {
+        for (j, (_, other)) in hashes.iter().enumerate().skip(i + 1) {
+            if (hash ^ other).count_ones() <= threshold {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_root = std::collections::HashMap::new();
+    for (i, (index, _)) in hashes.iter().enumerate() {
+        let group = *group_of_root.entry(root(&mut parents, i)).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(*index);
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
@@ -1,48 +1,167 @@
-use crate::gui::components::image_preview;
+use crate::color::ColorManagement;
+use crate::config::Config;
+use crate::contact_sheet::Pages;
+use crate::convert::ConvertOptions;
+use crate::exif::{Orientation, Transform};
+use crate::crop::{AspectRatio, CropRect};
+use crate::geometry::WindowGeometry;
+use crate::open_with::{self, Targets};
+use crate::session::Session;
+use crate::tags::{Query, TagDb, Tags};
+use crate::gui::components::{compare, crop_preview, diff_view, histogram_panel, image_preview};
+use crate::gui::duplicates;
+use crate::gui::components::{context_menu, prompt_bar, status_bar, PROMPT_ID};
+use crate::gui::keymap::{Action, Keymap};
+use crate::gui::vim::{Motion, PendingKeys, VimKey};
+use crate::gui::watcher::FileChange;
+use crate::gui::widgets::synced_viewer::ViewTransform;
+use crate::gui::widgets::wheel_zoom::WheelZoom;
+use crate::gui::scheduler::ThumbScheduler;
 use crate::gui::style;
 use crate::gui::types::*;
 use crate::gui::Message;
 use crate::utils::*;
+use crate::xmp::{self, Metadata};
 
-use iced::keyboard::KeyCode;
+use iced::keyboard::{KeyCode, Modifiers};
 use iced::widget::image::{Handle, Image};
 use iced::widget::scrollable::AbsoluteOffset;
-use iced::widget::{button, column, container, row, scrollable, text, Button};
-use iced::{theme, Command, Element};
+use iced::widget::{button, column, container, mouse_area, row, scrollable, text, text_input};
+use iced::{clipboard, theme, window, Command, Element, Point};
 use indexmap::IndexSet;
 use once_cell::sync::Lazy;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
 use std::io::{self, Write};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use style::{COLUMN_SPACING, CONTAINER_PADDING, DEFAULT_IMG_WIDTH, ROW_SPACING};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 static SCROLLABLE_ID: Lazy<scrollable::Id> = Lazy::new(scrollable::Id::unique);
-static COMMAND_COUNTER: AtomicUsize = AtomicUsize::new(0);
-const COMMANDS_NUM: usize = 4;
+const ZOOM_STEP: i32 = 20;
+/// Number of files hashed by each background task
+const HASH_BATCH: usize = 32;
+/// Number of sidecars read by each background task
+const SIDECAR_BATCH: usize = 256;
+const MAX_COMPARED: usize = 4;
+const COMPARE_ZOOM: f32 = 1.25;
+const ONION_STEP: f32 = 0.1;
+/// Names of the images left out of a contact sheet shown in the notice
+const SKIPPED_SHOWN: usize = 5;
+
+/// Top and bottom of each card of the gallery
+type CardBounds = Rc<[(u32, u32)]>;
 
 #[derive(Default)]
 pub struct Fuu {
     pub file_drag: bool,
     pub show_selections: bool,
+    /// Show the groups of similar images instead of the gallery
+    pub show_duplicates: bool,
+    /// Images that look alike, the largest one first
+    pub duplicate_groups: Vec<Vec<usize>>,
+    /// The duplicates are being grouped in the background
+    pub grouping_duplicates: bool,
+    /// More hashes came in while grouping, so it must be done again
+    pub regroup_duplicates: bool,
     pub current_page: Page,
     pub images: Vec<ImageCard>,
     pub container_dim: (u32, u32),
     pub img_width: u32,
     pub selected: usize,
     pub selections_list: IndexSet<usize>,
+    /// Indices in `images` of the cards of the gallery in order, cards are
+    /// never removed from `images` so indices stay valid for pending tasks
+    pub order: Vec<usize>,
+    /// Indices in `images` of the cards shown, in order
+    pub view: Vec<usize>,
+    /// Images rated below this are hidden
+    pub min_rating: u8,
+    pub tags: TagDb,
+    /// Only images whose tags match are shown
+    pub tag_query: Option<Query>,
+    /// Images whose content hash is being computed
+    pub hash_requests: HashSet<usize>,
+    /// Tag edits waiting for the content hash of their image
+    pub pending_tag_edits: HashMap<usize, String>,
+    /// Images whose sidecar is being read
+    pub metadata_requests: HashSet<usize>,
+    /// Text being typed in the status bar
+    pub prompt: Option<Prompt>,
+    /// Message shown in the status bar until the next key press
+    pub notice: Option<String>,
+    /// Marked images to compare, the first ones are shown
+    pub compare: Vec<usize>,
+    pub compare_transform: ViewTransform,
+    pub compare_mode: CompareMode,
+    /// Diff of the first two compared images
+    pub diff: Option<((usize, usize), Result<DiffPreview, String>)>,
+    /// Pair of images whose diff is being computed
+    pub diff_request: Option<(usize, usize)>,
+    pub onion_opacity: f32,
+    /// The onion skin blended with the current opacity
+    pub onion: Option<Preview>,
+    pub show_histogram: bool,
+    /// Histogram of the previewed image
+    pub histogram: Option<(usize, HistogramPreview)>,
+    pub histogram_request: Option<usize>,
+    /// Image being cropped and the rectangle drawn over it
+    pub crop: Option<(usize, CropRect)>,
+    pub crop_aspect: AspectRatio,
+    /// Options of the last conversion, the config ones at first
+    pub convert_options: ConvertOptions,
+    pub conversion: Option<Conversion>,
+    /// A contact sheet is being written
+    pub writing_contact_sheet: bool,
+    /// Files being rotated or flipped, with the changes asked since that are
+    /// applied together once the file is written
+    pub transform_requests: HashMap<PathBuf, Orientation>,
+    /// Quit was asked while files were written, it happens once they are
+    pub quitting: bool,
+    /// Commands of the config that are still running, by index
+    pub running_commands: Vec<usize>,
     pub current_scroll_offset: scrollable::AbsoluteOffset,
+    /// Card extents of the current layout and the card width they were computed for,
+    /// cleared by every message but scrolling
+    pub card_bounds_cache: RefCell<Option<(u32, CardBounds)>>,
+    pub color: ColorManagement,
+    pub show_unmanaged: bool,
+    pub preview_requests: HashSet<usize>,
+    /// Loaded previews from the least to the most recently used
+    pub loaded_previews: IndexSet<usize>,
+    pub thumb_scheduler: ThumbScheduler,
+    pub config: Config,
+    pub keymap: Keymap,
+    pub pending_keys: PendingKeys,
+    /// Position of the card whose context menu is open, and where it was opened
+    pub context_menu: Option<(usize, Point)>,
+    pub fullscreen: bool,
+    /// Last size and position of the window outside of fullscreen
+    pub window_geometry: WindowGeometry,
+    /// Sources given on the command line or dropped, as session keys
+    pub sources: Vec<String>,
+    /// Session to restore once the sources are loaded
+    pub pending_session: Option<Session>,
 }
 
 impl Fuu {
-    pub fn new() -> Self {
+    pub fn new(config: Config, color: ColorManagement, keymap: Keymap) -> Self {
         Self {
-            img_width: DEFAULT_IMG_WIDTH,
+            keymap,
+            img_width: config.layout.thumb_width,
+            color,
+            thumb_scheduler: ThumbScheduler::new(config.concurrency.thumb_workers),
+            convert_options: config.convert.clone(),
+            config,
+            onion_opacity: 0.5,
             ..Default::default()
         }
     }
 
     pub fn row_num(&self) -> usize {
-        let container_width = self.container_dim.0.max(self.img_width + CONTAINER_PADDING);
-        ((container_width - CONTAINER_PADDING) / self.img_width) as usize
+        let padding = self.config.layout.container_padding;
+        let container_width = self.container_dim.0.max(self.img_width + padding);
+        ((container_width - padding) / self.img_width) as usize
     }
 
     pub fn get_top(&self) -> usize {
@@ -56,7 +175,7 @@ impl Fuu {
 
     pub fn get_bottom(&self) -> usize {
         let row_num = self.row_num();
-        if self.selected + row_num < self.images.len() {
+        if self.selected + row_num < self.view_len() {
             self.selected + row_num
         } else {
             self.selected
@@ -64,49 +183,65 @@ impl Fuu {
     }
 
     pub fn get_forward(&self) -> usize {
-        (self.selected + 1).min(self.images.len() - 1)
+        (self.selected + 1).min(self.view_len() - 1)
     }
 
     pub fn get_backward(&self) -> usize {
         self.selected.max(1) - 1
     }
 
+    /// Height of the image and badges of a card, without its padding
+    fn card_height(&self, image_card: &ImageCard) -> u32 {
+        let badges = if self.has_badges(image_card) {
+            style::BADGE_HEIGHT
+        } else {
+            0
+        };
+        image_card.resize(self.img_width).1 + badges
+    }
+
     pub fn height_from_top(&self) -> u32 {
+        let layout = &self.config.layout;
         let row_num = self.row_num();
         let current_row = self.selected % row_num;
-        CONTAINER_PADDING
-            + self.images[current_row..self.selected]
+        layout.container_padding
+            + self.view[current_row..self.selected]
                 .iter()
                 .step_by(row_num)
-                .fold(0, |height, image| {
-                    height + image.resize(self.img_width).1 + COLUMN_SPACING
+                .fold(0, |height, &index| {
+                    height + self.card_height(&self.images[index]) + layout.column_spacing
                 })
     }
 
     pub fn height_from_bottom(&self) -> u32 {
+        let layout = &self.config.layout;
         let row_num = self.row_num();
         let current_row = self.selected % row_num;
-        CONTAINER_PADDING
-            + self.images[current_row..(self.selected + 1).min(self.images.len())]
+        layout.container_padding
+            + self.view[current_row..(self.selected + 1).min(self.view_len())]
                 .iter()
                 .step_by(row_num)
-                .fold(0, |height, image| {
-                    height + image.resize(self.img_width).1 + 2 * COLUMN_SPACING
+                .fold(0, |height, &index| {
+                    height + self.card_height(&self.images[index]) + 2 * layout.column_spacing
                 })
     }
 
     pub fn calculate_scroll_offset(&self) -> AbsoluteOffset {
+        if self.selected >= self.view_len() {
+            return self.current_scroll_offset;
+        }
         let height_from_bottom = self.height_from_bottom();
         let height_from_top = self.height_from_top();
         let height = self.container_dim.1;
         if height_from_bottom > height + self.current_scroll_offset.y as u32 {
+            let image_card = &self.images[self.image_index(self.selected)];
             AbsoluteOffset {
-                y: (height_from_bottom + self.images[self.selected].height - height) as f32,
+                y: (height_from_bottom + image_card.height).saturating_sub(height) as f32,
                 ..Default::default()
             }
         } else if height_from_top < self.current_scroll_offset.y as u32 {
             AbsoluteOffset {
-                y: (height_from_top - CONTAINER_PADDING) as f32,
+                y: (height_from_top - self.config.layout.container_padding) as f32,
                 ..Default::default()
             }
         } else {
@@ -114,6 +249,799 @@ impl Fuu {
         }
     }
 
+    /// Vertical extent of every card of the current view inside the gallery
+    fn card_bounds(&self) -> CardBounds {
+        if let Some((img_width, bounds)) = &*self.card_bounds_cache.borrow() {
+            if *img_width == self.img_width {
+                return bounds.clone();
+            }
+        }
+        let layout = &self.config.layout;
+        let row_num = self.row_num();
+        let mut column_heights = vec![layout.container_padding; row_num];
+        let bounds = (0..self.view_len())
+            .map(|position| {
+                let image_card = &self.images[self.image_index(position)];
+                let top = column_heights[position % row_num];
+                let bottom = top + self.card_height(image_card) + 2 * style::CARD_PADDING;
+                column_heights[position % row_num] = bottom + layout.column_spacing;
+                (top, bottom)
+            })
+            .collect::<Rc<[_]>>();
+        *self.card_bounds_cache.borrow_mut() = Some((self.img_width, bounds.clone()));
+        bounds
+    }
+
+    /// Position in the view of the card under `point`, in window coordinates
+    fn card_at(&self, point: Point) -> Option<usize> {
+        let layout = &self.config.layout;
+        let row_num = self.row_num() as u32;
+        let card_width = self.img_width + 2 * style::CARD_PADDING;
+        let column_width = card_width + layout.row_spacing;
+        let inner_width = self.container_dim.0.max(self.img_width + layout.container_padding)
+            - layout.container_padding;
+        let rows_width = 2 * layout.container_padding + row_num * column_width - layout.row_spacing;
+        let left = inner_width.saturating_sub(rows_width) / 2 + layout.container_padding;
+        let x = (point.x as u32).checked_sub(left)?;
+        let column = x / column_width;
+        if column >= row_num || x % column_width > card_width {
+            return None;
+        }
+        let y = point.y as u32 + self.current_scroll_offset.y as u32;
+        self.card_bounds()
+            .iter()
+            .enumerate()
+            .skip(column as usize)
+            .step_by(row_num as usize)
+            .find(|(_, (top, bottom))| (*top..*bottom).contains(&y))
+            .map(|(position, _)| position)
+    }
+
+    fn zoom(&mut self, step: i32) {
+        let min_width = (self.config.layout.thumb_width / 2).max(1);
+        let max_width = self.container_dim.0.max(min_width);
+        let width = self.img_width as i32 + step;
+        self.img_width = (width.max(0) as u32).clamp(min_width, max_width);
+    }
+
+    /// Zoom the gallery keeping the card under the cursor at the same place
+    fn zoom_at(&mut self, zoom_in: bool, cursor: Point) -> Command<Message> {
+        let anchor = self.card_at(cursor).map(|position| {
+            let (top, bottom) = self.card_bounds()[position];
+            let y = cursor.y + self.current_scroll_offset.y;
+            (position, (y - top as f32) / (bottom - top).max(1) as f32)
+        });
+        self.zoom(if zoom_in { ZOOM_STEP } else { -ZOOM_STEP });
+        let Some((position, fraction)) = anchor else {
+            return self.update_scroll_offset();
+        };
+        let (top, bottom) = self.card_bounds()[position];
+        let y = top as f32 + fraction * (bottom - top) as f32 - cursor.y;
+        self.current_scroll_offset = AbsoluteOffset { x: 0.0, y: y.max(0.0) };
+        Command::batch([
+            scrollable::scroll_to(SCROLLABLE_ID.clone(), self.current_scroll_offset),
+            self.schedule_thumbs(),
+        ])
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) -> Command<Message> {
+        self.fullscreen = fullscreen;
+        let mode = if fullscreen {
+            window::Mode::Fullscreen
+        } else {
+            window::Mode::Windowed
+        };
+        window::change_mode(mode)
+    }
+
+    pub fn session(&self) -> Session {
+        let view_len = self.view_len();
+        Session {
+            sources: self.sources.clone(),
+            selected: (self.selected < view_len)
+                .then(|| self.images[self.image_index(self.selected)].preview.key()),
+            marked: self
+                .selections_list
+                .iter()
+                .map(|&index| self.images[index].preview.key())
+                .collect(),
+            show_selections: self.show_selections,
+            scroll_offset: self.current_scroll_offset.y,
+        }
+    }
+
+    /// Go back to the state of `session`, images that no longer exist are skipped
+    fn restore_session(&mut self, session: Session) -> Command<Message> {
+        let indices: HashMap<String, usize> = self
+            .images
+            .iter()
+            .enumerate()
+            .map(|(index, image_card)| (image_card.preview.key(), index))
+            .collect();
+        self.selections_list = session
+            .marked
+            .iter()
+            .filter_map(|key| indices.get(key).copied())
+            .collect();
+        self.show_selections = session.show_selections && !self.selections_list.is_empty();
+        self.refresh_view();
+        let selected = session.selected.and_then(|key| indices.get(&key).copied());
+        self.selected = selected
+            .and_then(|index| self.view.iter().position(|&shown| shown == index))
+            .unwrap_or(0);
+        self.current_scroll_offset = AbsoluteOffset {
+            x: 0.0,
+            y: session.scroll_offset,
+        };
+        self.update_scroll_offset()
+    }
+
+    fn toggle_mark(&mut self, index: usize) {
+        if !self.selections_list.insert(index) {
+            self.selections_list.remove(&index);
+        }
+        if self.show_selections {
+            self.refresh_view();
+        }
+    }
+
+    /// Rebuild the list of cards shown, keeping the selected image when it's still part of it
+    fn refresh_view(&mut self) {
+        self.card_bounds_cache.get_mut().take();
+        let selected = self.view.get(self.selected).copied();
+        let images = &self.images;
+        let min_rating = self.min_rating;
+        let visible = |index: &usize| {
+            let image_card = &images[*index];
+            image_card.metadata.rating >= min_rating
+                && self
+                    .tag_query
+                    .as_ref()
+                    .is_none_or(|query| query.matches(self.card_tags(image_card)))
+        };
+        self.view = if self.show_duplicates {
+            self.duplicate_groups
+                .iter()
+                .flatten()
+                .copied()
+                .filter(visible)
+                .collect()
+        } else if self.show_selections {
+            self.selections_list.iter().copied().filter(visible).collect()
+        } else {
+            self.order.iter().copied().filter(visible).collect()
+        };
+        self.selected = selected
+            .and_then(|index| self.view.iter().position(|&shown| shown == index))
+            .unwrap_or_else(|| self.selected.min(self.view.len().saturating_sub(1)));
+    }
+
+    /// Directories given as sources, their content is kept in sync with the gallery
+    pub fn watched_dirs(&self) -> Vec<PathBuf> {
+        self.sources
+            .iter()
+            .map(PathBuf::from)
+            .filter(|path| path.is_dir())
+            .collect()
+    }
+
+    /// Add cards for the sources that aren't known yet, the indices of every source are returned
+    fn add_images(&mut self, sources: Vec<ImageSource>) -> Vec<usize> {
+        let mut image_cards: IndexSet<ImageCard> = self.images.drain(..).collect();
+        let added = sources
+            .into_iter()
+            .map(|source| {
+                let image_card = ImageCard::new(source).with_width(self.config.layout.thumb_width);
+                image_cards.insert_full(image_card).0
+            })
+            .collect();
+        self.images = image_cards.into_iter().collect();
+        added
+    }
+
+    /// Index of the local image at `path`, which may no longer exist
+    fn find_image(&self, path: &Path) -> Option<usize> {
+        let name = path.file_name()?;
+        let dir = canonical_parent(path)?;
+        let mut dirs = HashMap::new();
+        self.images.iter().position(|image_card| match &image_card.preview {
+            ImageSource::Path(image_path) => {
+                image_path.file_name() == Some(name)
+                    && dirs
+                        .entry(image_path.parent())
+                        .or_insert_with(|| canonical_parent(image_path))
+                        .as_ref()
+                        == Some(&dir)
+            }
+            ImageSource::Url(_) => false,
+        })
+    }
+
+    /// Put a new card in the gallery, before the first image of the same
+    /// directory whose name comes after it
+    fn insert_in_order(&mut self, index: usize) {
+        let ImageSource::Path(path) = &self.images[index].preview else {
+            self.order.push(index);
+            return;
+        };
+        let dir = canonical_parent(path);
+        let mut dirs = HashMap::new();
+        let mut last_of_dir = None;
+        let mut position = None;
+        for (order_position, &other) in self.order.iter().enumerate() {
+            let ImageSource::Path(other_path) = &self.images[other].preview else {
+                continue;
+            };
+            let other_dir = dirs
+                .entry(other_path.parent())
+                .or_insert_with(|| canonical_parent(other_path));
+            if *other_dir != dir {
+                continue;
+            }
+            if other_path.file_name() > path.file_name() {
+                position = Some(order_position);
+                break;
+            }
+            last_of_dir = Some(order_position + 1);
+        }
+        match position.or(last_of_dir) {
+            Some(position) => self.order.insert(position, index),
+            None => self.order.push(index),
+        }
+    }
+
+    /// Forget what was loaded from a file that changed on disk
+    fn invalidate(&mut self, index: usize) -> Command<Message> {
+        let image_card = &mut self.images[index];
+        image_card.remove_thumb();
+        image_card.preview_state = ImageState::Loading;
+        image_card.hash = None;
+        self.loaded_previews.shift_remove(&index);
+        if self.diff.as_ref().is_some_and(|((a, b), _)| *a == index || *b == index) {
+            self.diff = None;
+            self.onion = None;
+        }
+        if self.histogram.as_ref().is_some_and(|(computed, _)| *computed == index) {
+            self.histogram = None;
+        }
+        if let Page::ShowImage | Page::Compare = self.current_page {
+            return self.update_preview_data();
+        }
+        Command::none()
+    }
+
+    fn file_changed(&mut self, change: FileChange) -> Command<Message> {
+        match change {
+            FileChange::Created(path) | FileChange::Modified(path) => {
+                match self.find_image(&path) {
+                    Some(index) if self.order.contains(&index) => Command::batch([
+                        self.invalidate(index),
+                        self.schedule_thumbs(),
+                        self.hash_images(),
+                    ]),
+                    // a new file, or one that was removed and came back
+                    _ => {
+                        let sources = vec![ImageSource::Path(path)];
+                        Command::perform(read_sources(sources), Message::FilesAdded)
+                    }
+                }
+            }
+            FileChange::Removed(path) => {
+                let Some(index) = self.find_image(&path) else {
+                    return Command::none();
+                };
+                let Some(order_position) = self.order.iter().position(|&shown| shown == index)
+                else {
+                    return Command::none();
+                };
+                self.order.remove(order_position);
+                self.selections_list.shift_remove(&index);
+                for group in &mut self.duplicate_groups {
+                    group.retain(|&grouped| grouped != index);
+                }
+                self.compare.retain(|&compared| compared != index);
+                if let (Page::Compare, 0..=1) = (&self.current_page, self.compare.len()) {
+                    self.current_page = Page::Gallery;
+                }
+                self.loaded_previews.shift_remove(&index);
+                let image_card = &mut self.images[index];
+                image_card.preview_state = ImageState::Loading;
+                // nothing left to generate a thumbnail from
+                image_card.thumb_state = ThumbState::Error;
+                self.context_menu = None;
+                self.refresh_view();
+                self.reveal_selected()
+            }
+        }
+    }
+
+    /// Pixels then bytes of an image, to find the best copy of a duplicate
+    /// Group the images of the gallery that look alike in the background
+    fn find_duplicates(&mut self) -> Command<Message> {
+        if self.grouping_duplicates {
+            self.regroup_duplicates = true;
+            return Command::none();
+        }
+        self.grouping_duplicates = true;
+        let candidates: Vec<duplicates::Candidate> = self
+            .order
+            .iter()
+            .filter_map(|&index| {
+                let image_card = &self.images[index];
+                Some(duplicates::Candidate {
+                    index,
+                    dhash: image_card.dhash?,
+                    pixels: image_card
+                        .source_dim
+                        .map_or(0, |(width, height)| width as u64 * height as u64),
+                    path: match &image_card.preview {
+                        ImageSource::Path(path) => Some(path.clone()),
+                        ImageSource::Url(_) => None,
+                    },
+                })
+            })
+            .collect();
+        let threshold = self.config.duplicates.threshold;
+        Command::perform(group_duplicates(candidates, threshold), Message::DuplicatesGrouped)
+    }
+
+    /// Mark every duplicate but the largest image of its group
+    fn mark_duplicates(&mut self) {
+        let before = self.selections_list.len();
+        for group in &self.duplicate_groups {
+            self.selections_list.extend(group.iter().skip(1));
+        }
+        let marked = self.selections_list.len() - before;
+        self.notice = Some(format!("{marked} duplicates marked"));
+    }
+
+    fn has_badges(&self, image_card: &ImageCard) -> bool {
+        let metadata = &image_card.metadata;
+        metadata.rating > 0 || metadata.label.is_some() || self.card_tags(image_card).is_some()
+    }
+
+    fn card_tags(&self, image_card: &ImageCard) -> Option<&Tags> {
+        self.tags.get(image_card.hash.as_deref()?)
+    }
+
+    /// Compute the content hash of local images in the background, they're only
+    /// needed to look up tags
+    fn hash_images(&mut self) -> Command<Message> {
+        if self.tags.is_empty() {
+            return Command::none();
+        }
+        let files: Vec<(usize, PathBuf)> = self
+            .images
+            .iter()
+            .enumerate()
+            .filter(|(index, image_card)| {
+                image_card.hash.is_none() && !self.hash_requests.contains(index)
+            })
+            .filter_map(|(index, image_card)| match &image_card.preview {
+                ImageSource::Path(path) => Some((index, path.clone())),
+                ImageSource::Url(_) => None,
+            })
+            .collect();
+        self.hash_requests.extend(files.iter().map(|(index, _)| *index));
+        Command::batch(files.chunks(HASH_BATCH).map(|files| {
+            Command::perform(content_hashes(files.to_vec()), Message::HashesLoaded)
+        }))
+    }
+
+    /// Read the sidecars of the local images in the background, cards show
+    /// no rating until then
+    fn load_metadata(&mut self) -> Command<Message> {
+        let files: Vec<(usize, PathBuf)> = self
+            .images
+            .iter()
+            .enumerate()
+            .filter(|(index, image_card)| {
+                !image_card.metadata_loaded && !self.metadata_requests.contains(index)
+            })
+            .filter_map(|(index, image_card)| match &image_card.preview {
+                ImageSource::Path(path) => Some((index, path.clone())),
+                ImageSource::Url(_) => None,
+            })
+            .collect();
+        self.metadata_requests.extend(files.iter().map(|(index, _)| *index));
+        Command::batch(files.chunks(SIDECAR_BATCH).map(|files| {
+            Command::perform(read_sidecars(files.to_vec()), Message::MetadataLoaded)
+        }))
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind) -> Command<Message> {
+        let value = match kind {
+            PromptKind::Tags => String::new(),
+            PromptKind::Filter => self
+                .tag_query
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            PromptKind::Convert => self.convert_options.to_string(),
+        };
+        self.prompt = Some(Prompt::new(kind, value));
+        text_input::focus(PROMPT_ID.clone())
+    }
+
+    fn submit_prompt(&mut self) -> Command<Message> {
+        let Some(prompt) = self.prompt.take() else {
+            return Command::none();
+        };
+        match prompt.kind {
+            PromptKind::Tags => return self.edit_tags(&prompt.value),
+            PromptKind::Convert => return self.start_conversion(&prompt.value),
+            PromptKind::Filter if prompt.value.trim().is_empty() => self.tag_query = None,
+            PromptKind::Filter => match prompt.value.parse() {
+                Ok(query) => self.tag_query = Some(query),
+                Err(err) => {
+                    self.notice = Some(format!("invalid query: {err}"));
+                    return Command::none();
+                }
+            },
+        }
+        self.refresh_view();
+        self.reveal_selected()
+    }
+
+    fn start_conversion(&mut self, input: &str) -> Command<Message> {
+        if self.conversion.is_some() {
+            self.notice = Some(String::from("a conversion is already running"));
+            return Command::none();
+        }
+        let options: ConvertOptions = match input.parse() {
+            Ok(options) => options,
+            Err(err) => {
+                self.notice = Some(format!("invalid options: {err}"));
+                return Command::none();
+            }
+        };
+        self.convert_options = options.clone();
+        let images = self.selections_list.iter().copied().collect();
+        self.conversion = Some(Conversion::new(images, options));
+        self.convert_next()
+    }
+
+    fn convert_next(&mut self) -> Command<Message> {
+        let Some(conversion) = &mut self.conversion else {
+            return Command::none();
+        };
+        let started = conversion.start(self.config.concurrency.thumb_workers);
+        let options = conversion.options.clone();
+        Command::batch(
+            started
+                .into_iter()
+                .map(|index| {
+                    let source = self.images[index].preview.clone();
+                    Command::perform(convert_source(source, options.clone()), Message::Converted)
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// The marked images in the order of the gallery, the ones filtered out of
+    /// the view come last in the order they were marked
+    fn marked_in_gallery_order(&self) -> Vec<usize> {
+        let positions: HashMap<usize, usize> =
+            self.view.iter().enumerate().map(|(position, &index)| (index, position)).collect();
+        let mut marked: Vec<usize> = self.selections_list.iter().copied().collect();
+        marked.sort_by_key(|index| positions.get(index).copied().unwrap_or(usize::MAX));
+        marked
+    }
+
+    /// Start the program of the `[[commands]]` entry at `index` on the current
+    /// image, the marked ones or their directory
+    fn run_external(&mut self, index: usize) -> Command<Message> {
+        let Some(command) = self.config.commands.get(index) else {
+            return Command::none();
+        };
+        let current = self.previewed_image();
+        let marked = if self.selections_list.is_empty() {
+            current.into_iter().collect()
+        } else {
+            self.marked_in_gallery_order()
+        };
+        let argument = |index: usize| match &self.images[index].preview {
+            ImageSource::Path(path) => path.clone().into_os_string(),
+            ImageSource::Url(url) => OsString::from(url.as_str()),
+        };
+        let targets = Targets {
+            file: current.map(argument),
+            marked: marked.iter().map(|&index| argument(index)).collect(),
+            dir: current
+                .and_then(|index| match &self.images[index].preview {
+                    ImageSource::Path(path) => canonical_parent(path),
+                    ImageSource::Url(_) => None,
+                })
+                .map(PathBuf::into_os_string),
+        };
+        let arguments = match open_with::expand(&command.command, &targets) {
+            Ok(arguments) => arguments,
+            Err(err) => {
+                self.notice = Some(format!("cannot run {}: {}", command.name, err));
+                return Command::none();
+            }
+        };
+        // only the images given to the program may have been changed by it
+        let mut affected = Vec::new();
+        if open_with::uses_marked(&command.command) {
+            affected.extend(marked);
+        }
+        if open_with::uses_file(&command.command) {
+            affected.extend(current);
+        }
+        let affected = affected
+            .into_iter()
+            .filter_map(|index| match &self.images[index].preview {
+                ImageSource::Path(path) => Some(path.clone()),
+                ImageSource::Url(_) => None,
+            })
+            .collect();
+        self.running_commands.push(index);
+        Command::perform(run_command(arguments), move |result| {
+            Message::CommandFinished(index, affected, result)
+        })
+    }
+
+    /// Write contact sheet pages of the marked images in gallery order, or of
+    /// the whole gallery when none is marked
+    fn write_contact_sheet(&mut self) -> Command<Message> {
+        if self.writing_contact_sheet {
+            self.notice = Some(String::from("a contact sheet is already being written"));
+            return Command::none();
+        }
+        let images = if self.selections_list.is_empty() {
+            self.view.clone()
+        } else {
+            self.marked_in_gallery_order()
+        };
+        if images.is_empty() {
+            return Command::none();
+        }
+        self.writing_contact_sheet = true;
+        let sources = images.iter().map(|&index| self.images[index].preview.clone()).collect();
+        Command::perform(
+            contact_sheet(
+                sources,
+                self.config.contact_sheet.clone(),
+                self.config.concurrency.thumb_workers,
+            ),
+            Message::ContactSheetWritten,
+        )
+    }
+
+    /// Where the pages went, and which images were left out of them
+    fn contact_sheet_notice(&self, pages: &Pages) -> String {
+        let written = match pages.written.as_slice() {
+            [page] => format!("contact sheet written to {}", page.display()),
+            written => format!(
+                "{} contact sheet pages written to {}",
+                written.len(),
+                self.config.contact_sheet.dir.display()
+            ),
+        };
+        match pages.skipped.as_slice() {
+            [] => written,
+            [name] => format!("{written}, {name} could not be read"),
+            skipped => {
+                let mut names = skipped[..skipped.len().min(SKIPPED_SHOWN)].join(", ");
+                if skipped.len() > SKIPPED_SHOWN {
+                    names += &format!(" and {} more", skipped.len() - SKIPPED_SHOWN);
+                }
+                format!("{written}, {} images could not be read: {names}", skipped.len())
+            }
+        }
+    }
+
+    /// Rotate or flip the files of the marked images, or of the selected one
+    fn transform_images(&mut self, transform: Transform) -> Command<Message> {
+        let targets: Vec<usize> = if self.selections_list.is_empty() {
+            self.view.get(self.selected).copied().into_iter().collect()
+        } else {
+            self.selections_list.iter().copied().collect()
+        };
+        let mut commands = Vec::new();
+        for index in targets {
+            let ImageSource::Path(path) = self.images[index].preview.clone() else {
+                self.notice = Some(String::from("downloaded images cannot be rotated"));
+                continue;
+            };
+            match self.transform_requests.get_mut(&path) {
+                // a file is never written twice at once
+                Some(queued) => *queued = queued.then(transform),
+                None => {
+                    let change = Orientation::default().then(transform);
+                    commands.push(self.transform_file(path, change));
+                }
+            }
+        }
+        Command::batch(commands)
+    }
+
+    fn transform_file(&mut self, path: PathBuf, change: Orientation) -> Command<Message> {
+        self.transform_requests.insert(path.clone(), Orientation::default());
+        Command::perform(transform_file(path.clone(), change), move |result| {
+            Message::Transformed(path, result)
+        })
+    }
+
+    /// Apply the changes asked while `path` was written, the thumbnail is made
+    /// again once there are none left
+    fn transformed(
+        &mut self,
+        path: PathBuf,
+        result: Result<(String, String), String>,
+    ) -> Command<Message> {
+        let queued = self.transform_requests.remove(&path);
+        let (before, after) = match result {
+            Ok(hashes) => hashes,
+            Err(err) => {
+                // the queued changes would fail the same way
+                self.notice = Some(err);
+                return Command::none();
+            }
+        };
+        // tags are kept by content hash, which the new orientation changed
+        if self.tags.get(&before).is_some() {
+            self.tags.rename(&before, &after);
+            if let Err(err) = self.tags.save() {
+                self.notice = Some(format!("cannot save tags: {err}"));
+            }
+        }
+        match queued {
+            Some(change) if change != Orientation::default() && !self.quitting => {
+                self.transform_file(path, change)
+            }
+            // it brings the new dimensions
+            _ => match self.find_image(&path) {
+                Some(index) => Command::batch([
+                    self.invalidate(index),
+                    self.schedule_thumbs(),
+                    self.hash_images(),
+                ]),
+                None => Command::none(),
+            },
+        }
+    }
+
+    /// Whether quitting now would leave files half written
+    fn writing_files(&self) -> bool {
+        !self.transform_requests.is_empty()
+            || self.conversion.is_some()
+            || self.writing_contact_sheet
+    }
+
+    /// Quit if it was asked while files were written and they all are
+    fn quit_when_written(&self) -> Command<Message> {
+        if self.quitting && !self.writing_files() {
+            return Command::perform(async {}, |_| Message::CloseRequested);
+        }
+        Command::none()
+    }
+
+    /// Add or remove tags of the selected image, once its content hash is known
+    fn edit_tags(&mut self, input: &str) -> Command<Message> {
+        let Some(&index) = self.view.get(self.selected) else {
+            return Command::none();
+        };
+        let image_card = &self.images[index];
+        let ImageSource::Path(path) = &image_card.preview else {
+            self.notice = Some(String::from("only local files can be tagged"));
+            return Command::none();
+        };
+        if image_card.hash.is_some() {
+            return self.apply_tag_edit(index, input);
+        }
+        let path = path.clone();
+        let edit = self.pending_tag_edits.entry(index).or_default();
+        edit.push(' ');
+        edit.push_str(input);
+        if !self.hash_requests.insert(index) {
+            // applied when the running hash is done
+            return Command::none();
+        }
+        Command::perform(content_hashes(vec![(index, path)]), Message::HashesLoaded)
+    }
+
+    fn apply_tag_edit(&mut self, index: usize, input: &str) -> Command<Message> {
+        let Some(hash) = self.images[index].hash.clone() else {
+            return Command::none();
+        };
+        self.tags.edit(&hash, input);
+        if let Err(err) = self.tags.save() {
+            self.notice = Some(format!("cannot save tags: {err}"));
+        }
+        self.refresh_view();
+        Command::batch([self.hash_images(), self.reveal_selected()])
+    }
+
+    /// Change the rating or label of the selected image and write them to its sidecar
+    fn update_metadata(&mut self, update: impl FnOnce(&mut Metadata)) -> Command<Message> {
+        let Some(&index) = self.view.get(self.selected) else {
+            return Command::none();
+        };
+        let image_card = &mut self.images[index];
+        if let (false, ImageSource::Path(path)) = (image_card.metadata_loaded, &image_card.preview) {
+            // the label must not be lost when the rating is written before the sidecar was read
+            image_card.metadata = xmp::read(path);
+            image_card.metadata_loaded = true;
+        }
+        let mut metadata = image_card.metadata;
+        update(&mut metadata);
+        if let ImageSource::Path(path) = &image_card.preview {
+            // the rating shown must be the one saved
+            if let Err(err) = xmp::write(path, metadata) {
+                let path = path.display();
+                self.notice = Some(format!("cannot write the sidecar of {path}: {err}"));
+                return Command::none();
+            }
+        }
+        image_card.metadata = metadata;
+        self.refresh_view();
+        self.reveal_selected()
+    }
+
+    fn card_action(&mut self, action: CardAction, position: usize) -> Command<Message> {
+        self.context_menu = None;
+        if position >= self.view_len() {
+            return Command::none();
+        }
+        let index = self.image_index(position);
+        match action {
+            CardAction::Open => {
+                self.selected = position;
+                self.current_page = Page::ShowImage;
+                return self.update_preview_data();
+            }
+            CardAction::Mark => self.toggle_mark(index),
+            CardAction::CopyPath => {
+                let path = match &self.images[index].preview {
+                    ImageSource::Path(path) => path.display().to_string(),
+                    ImageSource::Url(url) => url.to_string(),
+                };
+                return clipboard::write(path);
+            }
+            CardAction::OpenFolder => {
+                if let ImageSource::Path(path) = &self.images[index].preview {
+                    if let Some(parent) = path.parent() {
+                        open_in_file_manager(parent);
+                    }
+                }
+            }
+            CardAction::ReloadThumbnail => {
+                self.images[index].remove_thumb();
+                return self.schedule_thumbs();
+            }
+        }
+        Command::none()
+    }
+
+    /// Start loading the thumbnails closest to the visible part of the gallery
+    fn schedule_thumbs(&mut self) -> Command<Message> {
+        let view_top = self.current_scroll_offset.y as u32;
+        let view_bottom = view_top + self.container_dim.1;
+        let mut priorities = vec![u32::MAX; self.images.len()];
+        for (position, &(top, bottom)) in self.card_bounds().iter().enumerate() {
+            let distance = top.saturating_sub(view_bottom) + view_top.saturating_sub(bottom);
+            priorities[self.image_index(position)] = distance;
+        }
+        let mut candidates: Vec<usize> = self
+            .images
+            .iter()
+            .enumerate()
+            .filter(|(index, image_card)| {
+                matches!(image_card.thumb_state, ThumbState::Loading)
+                    && !self.thumb_scheduler.is_running(*index)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        candidates.sort_by_key(|&index| priorities[index]);
+        let started = self.thumb_scheduler.schedule(candidates);
+        Command::batch(started.into_iter().map(|index| {
+            let thumb = generate_thumb(self.images[index].clone(), self.color.clone());
+            Command::perform(thumb, move |dim| Message::ThumbLoaded(dim, index))
+        }))
+    }
+
     fn update_scroll_offset(&mut self) -> Command<Message> {
         self.current_scroll_offset = self.calculate_scroll_offset();
         if let Page::Gallery = self.current_page {
@@ -122,50 +1050,281 @@ impl Fuu {
         Command::none()
     }
 
-    fn update_preview_data(&self) -> Command<Message> {
-        let index = if self.show_selections {
-            self.selections_list[self.selected]
+    fn preview_color(&self) -> ColorManagement {
+        if self.show_unmanaged {
+            self.color.unmanaged()
         } else {
-            self.selected
-        };
+            self.color.clone()
+        }
+    }
+
+    /// Number of cards shown by the current view
+    pub fn view_len(&self) -> usize {
+        self.view.len()
+    }
+
+    /// Index in `images` of the card at `position` in the current view
+    pub fn image_index(&self, position: usize) -> usize {
+        self.view[position]
+    }
+
+    /// Images around the selected one that should be kept loaded, closest first
+    fn preview_window(&self) -> Vec<usize> {
+        if let Page::Compare = self.current_page {
+            return self.compare_panes().to_vec();
+        }
+        let view_len = self.view_len();
+        if view_len == 0 {
+            return Vec::new();
+        }
+        let selected = self.selected.min(view_len - 1);
+        let mut window = vec![self.image_index(selected)];
+        for distance in 1..=self.config.concurrency.prefetch {
+            if selected + distance < view_len {
+                window.push(self.image_index(selected + distance));
+            }
+            if selected >= distance {
+                window.push(self.image_index(selected - distance));
+            }
+        }
+        window
+    }
+
+    fn fetch_preview(&mut self, index: usize) -> Command<Message> {
         let image_card = &self.images[index];
-        match image_card.preview_state {
-            ImageState::Loading => {
-                let source = image_card.preview.clone();
-                Command::perform(fetch_source(source), move |rgba_image| {
-                    Message::PreviewLoaded(rgba_image, index)
-                })
+        if !matches!(image_card.preview_state, ImageState::Loading)
+            || !self.preview_requests.insert(index)
+        {
+            return Command::none();
+        }
+        let source = image_card.preview.clone();
+        let unmanaged = self.show_unmanaged;
+        Command::perform(fetch_source(source, self.preview_color()), move |preview| {
+            Message::PreviewLoaded(preview, index, unmanaged)
+        })
+    }
+
+    fn touch_preview(&mut self, index: usize) {
+        self.loaded_previews.shift_remove(&index);
+        self.loaded_previews.insert(index);
+    }
+
+    /// Drop the least recently used previews until they fit in the memory budget
+    fn evict_previews(&mut self) {
+        let window = self.preview_window();
+        let preview_size = |image_card: &ImageCard| match &image_card.preview_state {
+            ImageState::Loaded(preview) => preview.size(),
+            _ => 0,
+        };
+        let mut total: usize = self
+            .loaded_previews
+            .iter()
+            .map(|&index| preview_size(&self.images[index]))
+            .sum();
+        let mut position = 0;
+        let budget = self.config.preview_budget();
+        while total > budget && position < self.loaded_previews.len() {
+            let index = self.loaded_previews[position];
+            if window.contains(&index) {
+                position += 1;
+                continue;
             }
-            _ => Command::none(),
+            total -= preview_size(&self.images[index]);
+            self.images[index].preview_state = ImageState::Loading;
+            self.loaded_previews.shift_remove_index(position);
+        }
+    }
+
+    /// Load the current image first, its neighbors are prefetched once it's done
+    fn update_preview_data(&mut self) -> Command<Message> {
+        let window = self.preview_window();
+        if let Page::Compare = self.current_page {
+            // every pane is needed at once
+            for &index in &window {
+                if let ImageState::Loaded(_) = self.images[index].preview_state {
+                    self.touch_preview(index);
+                }
+            }
+            let mut commands: Vec<_> =
+                window.into_iter().map(|index| self.fetch_preview(index)).collect();
+            commands.push(self.update_diff());
+            return Command::batch(commands);
+        }
+        let Some(&current) = window.first() else {
+            return Command::none();
+        };
+        match self.images[current].preview_state {
+            ImageState::Loading => return self.fetch_preview(current),
+            ImageState::Loaded(_) => self.touch_preview(current),
+            ImageState::Error => (),
         }
+        let mut commands: Vec<_> =
+            window[1..].iter().map(|&index| self.fetch_preview(index)).collect();
+        commands.push(self.update_histogram(current));
+        Command::batch(commands)
+    }
+
+    fn update_histogram(&mut self, index: usize) -> Command<Message> {
+        if !self.show_histogram
+            || !matches!(self.current_page, Page::ShowImage)
+            || self.histogram.as_ref().is_some_and(|(computed, _)| *computed == index)
+            || self.histogram_request == Some(index)
+        {
+            return Command::none();
+        }
+        let ImageState::Loaded(preview) = &self.images[index].preview_state else {
+            return Command::none();
+        };
+        let preview = preview.clone();
+        self.histogram_request = Some(index);
+        Command::perform(histogram(preview), move |histogram| {
+            Message::HistogramComputed(index, histogram)
+        })
+    }
+
+    /// Index of the image shown by the preview page
+    fn previewed_image(&self) -> Option<usize> {
+        let view_len = self.view_len();
+        (view_len > 0).then(|| self.image_index(self.selected.min(view_len - 1)))
     }
 
     pub fn image_preview(&self) -> Element<Message> {
-        let image_card = if self.show_selections {
-            let index = self.selected.min(self.selections_list.len() - 1);
-            &self.images[self.selections_list[index]]
+        let view_len = self.view_len();
+        if view_len == 0 {
+            return container(text("no image left in this view"))
+                .width(self.container_dim.0 as u16)
+                .height(self.container_dim.1 as u16)
+                .center_x()
+                .center_y()
+                .into();
+        }
+        let index = self.image_index(self.selected.min(view_len - 1));
+        match (self.crop, &self.images[index].preview_state) {
+            (Some((cropped, rect)), ImageState::Loaded(preview)) if cropped == index => {
+                return crop_preview(preview, rect, self.crop_aspect, self.container_dim);
+            }
+            _ => (),
+        }
+        let panel = self.show_histogram.then(|| {
+            let histogram = match &self.histogram {
+                Some((computed, histogram)) if *computed == index => Some(histogram),
+                _ => None,
+            };
+            histogram_panel(histogram)
+        });
+        image_preview(&self.images[index], self.container_dim, panel)
+    }
+
+    pub fn status_view(&self) -> Element<Message> {
+        if let Some(prompt) = &self.prompt {
+            return prompt_bar(prompt);
+        }
+        let view_len = self.view_len();
+        let mut status = if view_len == 0 {
+            String::from("0/0")
         } else {
-            &self.images[self.selected]
+            let selected = self.selected.min(view_len - 1);
+            let metadata = &self.images[self.image_index(selected)].metadata;
+            let mut status = format!("{}/{}", selected + 1, view_len);
+            if metadata.rating > 0 {
+                status += &format!("  {}", stars(metadata.rating));
+            }
+            if let Some(label) = metadata.label {
+                status += &format!("  {label}");
+            }
+            if let Some(tags) = self.card_tags(&self.images[self.image_index(selected)]) {
+                status += &format!("  {}", tag_list(tags));
+            }
+            status
         };
-        image_preview(image_card, self.container_dim)
+        if !self.selections_list.is_empty() {
+            status += &format!("  {} marked", self.selections_list.len());
+        }
+        if self.show_selections {
+            status += "  [selections]";
+        }
+        if self.show_duplicates {
+            let selected = self.view.get(self.selected);
+            let group = self
+                .duplicate_groups
+                .iter()
+                .position(|group| selected.is_some_and(|index| group.contains(index)));
+            let groups = self.duplicate_groups.len();
+            status += &match group {
+                Some(group) => format!("  [duplicates {}/{}]", group + 1, groups),
+                None if self.grouping_duplicates && groups == 0 => {
+                    String::from("  [finding duplicates]")
+                }
+                None => format!("  [duplicates, {groups} groups]"),
+            };
+        }
+        if self.min_rating > 0 {
+            status += &format!("  [{} and up]", stars(self.min_rating));
+        }
+        if let Some(query) = &self.tag_query {
+            status += &format!("  [{query}]");
+        }
+        if self.show_unmanaged {
+            status += "  [unmanaged colors]";
+        }
+        if let Some((_, rect)) = self.crop {
+            status += &format!("  [crop {rect}, {}]", self.crop_aspect);
+        }
+        if let Some(conversion) = &self.conversion {
+            status += &format!("  [{conversion}]");
+        }
+        if self.writing_contact_sheet {
+            status += "  [writing a contact sheet]";
+        }
+        if !self.running_commands.is_empty() {
+            let names: Vec<&str> = self
+                .running_commands
+                .iter()
+                .filter_map(|&index| self.config.commands.get(index))
+                .map(|command| command.name.as_str())
+                .collect();
+            status += &format!("  [running {}]", names.join(", "));
+        }
+        if let Page::Compare = self.current_page {
+            let mode = match self.compare_mode {
+                CompareMode::SideBySide => String::new(),
+                CompareMode::Heatmap => String::from(", heatmap"),
+                CompareMode::OnionSkin => {
+                    format!(", onion skin {:.0}%", self.onion_opacity * 100.0)
+                }
+            };
+            status += &format!(
+                "  [compare {} of {}{mode}, {:.0}%]",
+                self.compare_panes().len(),
+                self.compare.len(),
+                self.compare_transform.scale * 100.0
+            );
+        }
+        if let Some(notice) = &self.notice {
+            status += &format!("  {notice}");
+        }
+        status_bar(status, self.pending_keys.to_string())
     }
 
-    fn card_style(&self, index: usize) -> theme::Button {
-        if index == self.selected {
+    pub fn context_menu_view(&self, position: usize) -> Element<Message> {
+        let image_card = &self.images[self.image_index(position)];
+        let marked = self.selections_list.contains(&self.image_index(position));
+        let is_file = matches!(image_card.preview, ImageSource::Path(_));
+        context_menu(position, marked, is_file)
+    }
+
+    fn card_style(&self, position: usize) -> theme::Button {
+        if position == self.selected {
             return theme::Button::Custom(Box::new(style::ImageCard::Hovered));
         }
-        if self.show_selections || self.selections_list.contains(&index) {
+        if self.show_selections || self.selections_list.contains(&self.image_index(position)) {
             return theme::Button::Custom(Box::new(style::ImageCard::Selected));
         }
         theme::Button::Custom(Box::new(style::ImageCard::Normal))
     }
 
-    fn card_view(&self, index: usize) -> Button<Message> {
-        let image_card = if self.show_selections {
-            &self.images[self.selections_list[index]]
-        } else {
-            &self.images[index]
-        };
+    fn card_view(&self, position: usize) -> Element<Message> {
+        let image_card = &self.images[self.image_index(position)];
         let (w, h) = image_card.resize(self.img_width);
         let content = match &image_card.thumb_state {
             ThumbState::Loading => Element::new(
@@ -188,25 +1347,34 @@ impl Fuu {
                     .height(h as u16),
             ),
         };
-        button(content)
-            .on_press(Message::ChangeFocus(index))
-            .style(self.card_style(index))
+        let content = if self.has_badges(image_card) {
+            let tags = self.card_tags(image_card).map(tag_list).unwrap_or_default();
+            // about the number of characters fitting under the thumbnail
+            let tags = tags.chars().take((w / 7) as usize).collect();
+            column![content, badges(&image_card.metadata, tags)].into()
+        } else {
+            content
+        };
+        let card = button(content)
+            .padding(style::CARD_PADDING as u16)
+            .on_press(Message::ChangeFocus(position))
+            .style(self.card_style(position));
+        mouse_area(card)
+            .on_right_press(Message::ContextMenu(position))
+            .into()
     }
 
     pub fn gallery_view(&self) -> Element<Message> {
+        let layout = &self.config.layout;
         let row_num = self.row_num();
-        let elem_num = if self.show_selections {
-            self.selections_list.len()
-        } else {
-            self.images.len()
-        };
+        let elem_num = self.view_len();
         let mut remaining = elem_num % row_num;
         let mut rows = row![]
-            .spacing(ROW_SPACING as u16)
-            .padding(CONTAINER_PADDING as u16);
+            .spacing(layout.row_spacing as u16)
+            .padding(layout.container_padding as u16);
 
         for i in 0..row_num {
-            let mut columns = column![].spacing(COLUMN_SPACING as u16);
+            let mut columns = column![].spacing(layout.column_spacing as u16);
             let mut column_num = elem_num / row_num;
             if remaining != 0 {
                 remaining -= 1;
@@ -218,79 +1386,349 @@ impl Fuu {
             rows = rows.push(columns);
         }
 
-        let container_width = self.container_dim.0.max(self.img_width + CONTAINER_PADDING) as u16;
+        let container_width =
+            self.container_dim.0.max(self.img_width + layout.container_padding) as u16;
 
         let content = container(rows)
-            .width(container_width - CONTAINER_PADDING as u16)
+            .width(container_width - layout.container_padding as u16)
             .center_x();
 
-        scrollable(content)
+        let gallery = scrollable(content)
             .id(SCROLLABLE_ID.clone())
+            .on_scroll(Message::Scrolled)
             .width(container_width)
-            .height(self.container_dim.1 as u16)
-            .into()
+            .height(self.container_dim.1 as u16);
+        WheelZoom::new(gallery, Message::WheelZoom).into()
     }
 
-    fn handle_keypress(&mut self, key: KeyCode) -> Command<Message> {
-        if let Page::Welcome | Page::Error(_) = self.current_page {
-            return Command::none()
+    /// Bring the selected card into view, or show its preview
+    fn reveal_selected(&mut self) -> Command<Message> {
+        match self.current_page {
+            Page::Gallery => self.update_scroll_offset(),
+            Page::ShowImage => self.update_preview_data(),
+            _ => Command::none(),
         }
-        match key {
-            KeyCode::Plus | KeyCode::NumpadAdd => {
-                self.img_width += 20;
-                self.img_width = self.img_width.min(self.container_dim.0);
-                return self.update_scroll_offset();
+    }
+
+    /// Move the selection `count` times in the direction of `action`,
+    /// or until it reaches the edge of the gallery
+    fn move_selection(&mut self, action: Action, count: usize) -> Command<Message> {
+        let view_len = self.view_len();
+        if view_len == 0 {
+            return Command::none();
+        }
+        for _ in 0..count {
+            let selected = match action {
+                Action::Next => self.get_forward(),
+                Action::Previous => self.get_backward(),
+                Action::Up => self.get_top(),
+                Action::Down => self.get_bottom(),
+                _ => self.selected,
+            };
+            let selected = selected.min(view_len - 1);
+            if selected == self.selected {
+                break;
             }
-            KeyCode::Minus | KeyCode::NumpadSubtract => {
-                self.img_width -= 20;
-                self.img_width = self.img_width.max(DEFAULT_IMG_WIDTH / 2);
+            self.selected = selected;
+        }
+        self.reveal_selected()
+    }
+
+    fn apply_motion(&mut self, motion: Motion) -> Command<Message> {
+        let view_len = self.view_len();
+        if view_len == 0 {
+            return Command::none();
+        }
+        match motion {
+            Motion::Repeat(action, count) => return self.move_selection(action, count),
+            Motion::Jump(position) => {
+                self.selected = position.unwrap_or(view_len - 1).min(view_len - 1);
+            }
+            Motion::HalfPage(forward, count) => {
+                let card_height = self.img_width + self.config.layout.column_spacing;
+                let rows = (self.container_dim.1 / 2 / card_height.max(1)).max(1) as usize;
+                let distance = (rows * self.row_num()).saturating_mul(count);
+                self.selected = if forward {
+                    (self.selected + distance).min(view_len - 1)
+                } else {
+                    self.selected.saturating_sub(distance)
+                };
+            }
+        }
+        self.reveal_selected()
+    }
+
+    fn handle_keypress(&mut self, key: KeyCode, modifiers: Modifiers) -> Command<Message> {
+        if self.prompt.is_some() {
+            // the text input gets the other keys
+            if key == KeyCode::Escape {
+                self.prompt = None;
+            }
+            return Command::none();
+        }
+        self.notice = None;
+        if self.context_menu.take().is_some() {
+            return Command::none();
+        }
+        if self.config.vim && matches!(self.current_page, Page::Gallery | Page::ShowImage) {
+            match self.pending_keys.feed(key, modifiers) {
+                VimKey::Pending => return Command::none(),
+                VimKey::Motion(motion) => return self.apply_motion(motion),
+                VimKey::Unhandled => (),
+            }
+        }
+        match self.keymap.action(key, modifiers) {
+            Some(action) => self.perform_action(action),
+            None => Command::none(),
+        }
+    }
+
+    /// Reload the previews with or without color management
+    fn toggle_color_management(&mut self) {
+        self.show_unmanaged ^= true;
+        for index in self.loaded_previews.drain(..) {
+            self.images[index].preview_state = ImageState::Loading;
+        }
+        self.diff = None;
+        self.onion = None;
+        self.histogram = None;
+    }
+
+    /// Images of the compare page that are shown
+    fn compare_panes(&self) -> &[usize] {
+        &self.compare[..self.compare.len().min(MAX_COMPARED)]
+    }
+
+    fn open_compare(&mut self) -> Command<Message> {
+        if self.selections_list.len() < 2 {
+            self.notice = Some(format!("mark 2 to {MAX_COMPARED} images to compare"));
+            return Command::none();
+        }
+        self.compare = self.selections_list.iter().copied().collect();
+        self.compare_transform = ViewTransform::default();
+        self.current_page = Page::Compare;
+        self.update_preview_data()
+    }
+
+    fn compare_action(&mut self, action: Action) -> Command<Message> {
+        match action {
+            // the marked images take turns in the panes
+            Action::Next => self.compare.rotate_left(1),
+            Action::Previous => self.compare.rotate_right(1),
+            Action::ZoomIn => self.compare_transform = self.compare_transform.zoom(COMPARE_ZOOM),
+            Action::ZoomOut => {
+                self.compare_transform = self.compare_transform.zoom(1.0 / COMPARE_ZOOM);
+            }
+            Action::ZoomReset => self.compare_transform = ViewTransform::default(),
+            Action::ToggleDiff => self.compare_mode = self.compare_mode.next(),
+            Action::Up | Action::Down if self.compare_mode == CompareMode::OnionSkin => {
+                let step = if action == Action::Up { ONION_STEP } else { -ONION_STEP };
+                return self.set_onion_opacity(self.onion_opacity + step);
+            }
+            Action::ToggleColorManagement => {
+                self.toggle_color_management();
+            }
+            Action::Open | Action::Back | Action::Compare => {
+                self.current_page = Page::Gallery;
                 return self.update_scroll_offset();
             }
-            KeyCode::Left | KeyCode::P => {
-                self.selected = self.get_backward();
-                if self.show_selections {
-                    self.selected = self.selected.min(self.selections_list.len() - 1)
+            _ => return Command::none(),
+        }
+        self.update_preview_data()
+    }
+
+    /// The diff of the first two panes, computed when a diff mode needs it
+    fn diff_pair(&self) -> Option<(usize, usize)> {
+        match (self.compare_mode, self.compare_panes()) {
+            (CompareMode::SideBySide, _) => None,
+            (_, [first, second, ..]) => Some((*first, *second)),
+            _ => None,
+        }
+    }
+
+    fn update_diff(&mut self) -> Command<Message> {
+        let Some(pair) = self.diff_pair() else {
+            return Command::none();
+        };
+        if self.diff.as_ref().is_some_and(|(computed, _)| *computed == pair) {
+            return match self.onion {
+                None if self.compare_mode == CompareMode::OnionSkin => self.blend_onion(),
+                _ => Command::none(),
+            };
+        }
+        if self.diff_request == Some(pair) {
+            return Command::none();
+        }
+        let (ImageState::Loaded(first), ImageState::Loaded(second)) =
+            (&self.images[pair.0].preview_state, &self.images[pair.1].preview_state)
+        else {
+            return Command::none();
+        };
+        let (first, second) = (first.clone(), second.clone());
+        self.diff_request = Some(pair);
+        Command::perform(diff_previews(first, second), move |result| {
+            Message::DiffComputed(pair, result)
+        })
+    }
+
+    fn blend_onion(&self) -> Command<Message> {
+        let Some((_, Ok(diff))) = &self.diff else {
+            return Command::none();
+        };
+        let diff = diff.clone();
+        let opacity = self.onion_opacity;
+        Command::perform(onion_skin(diff, opacity), move |preview| {
+            Message::OnionBlended(opacity, preview)
+        })
+    }
+
+    fn set_onion_opacity(&mut self, opacity: f32) -> Command<Message> {
+        self.onion_opacity = opacity.clamp(0.0, 1.0);
+        self.blend_onion()
+    }
+
+    pub fn compare_view(&self) -> Element<Message> {
+        if self.compare_mode != CompareMode::SideBySide {
+            let diff = match &self.diff {
+                Some((pair, diff)) if self.diff_pair() == Some(*pair) => Some(diff),
+                _ => None,
+            };
+            let (image, status) = match (diff, self.compare_mode) {
+                (Some(Ok(diff)), CompareMode::Heatmap) => {
+                    (Some(&diff.heatmap), diff.diff.to_string())
                 }
-                match self.current_page {
-                    Page::Gallery => return self.update_scroll_offset(),
-                    Page::ShowImage => return self.update_preview_data(),
-                    _ => (),
+                (Some(Ok(diff)), _) => (self.onion.as_ref(), diff.diff.to_string()),
+                (Some(Err(err)), _) => (None, err.clone()),
+                (None, _) if self.compare_panes().len() < 2 => {
+                    (None, String::from("nothing to diff"))
                 }
+                (None, _) => (None, String::from("computing the difference ...")),
+            };
+            let opacity = (self.compare_mode == CompareMode::OnionSkin)
+                .then_some(self.onion_opacity);
+            return diff_view(
+                image,
+                status,
+                self.compare_transform,
+                opacity,
+                self.container_dim,
+            );
+        }
+        let image_cards = self
+            .compare_panes()
+            .iter()
+            .map(|&index| &self.images[index])
+            .collect();
+        compare(image_cards, self.compare_transform, self.container_dim)
+    }
+
+    fn start_crop(&mut self) -> Command<Message> {
+        let Some(index) = self.previewed_image() else {
+            return Command::none();
+        };
+        match &self.images[index].preview_state {
+            ImageState::Loaded(preview) => {
+                let rect = CropRect::centered((preview.width, preview.height), self.crop_aspect);
+                self.crop = Some((index, rect));
             }
-            KeyCode::Right | KeyCode::N => {
-                self.selected = self.get_forward();
-                if self.show_selections {
-                    self.selected = self.selected.min(self.selections_list.len() - 1)
-                }
-                match self.current_page {
-                    Page::Gallery => return self.update_scroll_offset(),
-                    Page::ShowImage => return self.update_preview_data(),
-                    _ => (),
-                }
+            ImageState::Loading => self.notice = Some(String::from("the image is still loading")),
+            ImageState::Error => self.notice = Some(String::from("cannot crop this image")),
+        }
+        Command::none()
+    }
+
+    fn crop_action(&mut self, action: Action) -> Command<Message> {
+        let Some((index, rect)) = self.crop else {
+            return Command::none();
+        };
+        if let Action::Back | Action::Crop = action {
+            self.crop = None;
+            return Command::none();
+        }
+        let ImageState::Loaded(preview) = &self.images[index].preview_state else {
+            return Command::none();
+        };
+        let dim = (preview.width, preview.height);
+        let step = (dim.0.max(dim.1) / 100).max(1) as i32;
+        let rect = match action {
+            Action::Next => rect.moved(step, 0, dim),
+            Action::Previous => rect.moved(-step, 0, dim),
+            Action::Up => rect.moved(0, -step, dim),
+            Action::Down => rect.moved(0, step, dim),
+            Action::ZoomIn => rect.grown(step, self.crop_aspect, dim),
+            Action::ZoomOut => rect.grown(-step, self.crop_aspect, dim),
+            Action::CropAspect => {
+                self.crop_aspect = self.crop_aspect.next();
+                rect.with_aspect(self.crop_aspect, dim)
             }
-            KeyCode::Up => {
-                self.selected = self.get_top();
-                if self.show_selections {
-                    self.selected = self.selected.min(self.selections_list.len() - 1)
-                }
-                if let Page::Gallery = self.current_page {
-                    return self.update_scroll_offset();
-                }
+            Action::Open => return self.export_crop(false),
+            Action::CropToClipboard => return self.export_crop(true),
+            _ => return Command::none(),
+        };
+        self.crop = Some((index, rect));
+        Command::none()
+    }
+
+    /// Write the crop next to its image, or to the clipboard directory for
+    /// downloaded images and when asked to
+    fn export_crop(&mut self, to_clipboard: bool) -> Command<Message> {
+        let Some((index, rect)) = self.crop else {
+            return Command::none();
+        };
+        let source = self.images[index].preview.clone();
+        let path = match &source {
+            ImageSource::Path(path) => path.clone(),
+            ImageSource::Url(url) => PathBuf::from(url.path()),
+        };
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("image"));
+        let dir = match (&source, path.parent()) {
+            (ImageSource::Path(_), Some(parent)) if !to_clipboard => parent.to_path_buf(),
+            _ => self.config.crop.clipboard_dir.clone(),
+        };
+        Command::perform(write_crop(source, rect, dir, stem), move |result| {
+            Message::CropExported(result, to_clipboard)
+        })
+    }
+
+    pub fn perform_action(&mut self, action: Action) -> Command<Message> {
+        match action {
+            Action::Quit => return Command::perform(async {}, |_| Message::CloseRequested),
+            Action::ToggleFullscreen => return self.set_fullscreen(!self.fullscreen),
+            _ => (),
+        }
+        if let Some((index, _)) = self.crop {
+            // the image may have been removed while it was cropped
+            let previewed = self.previewed_image();
+            if matches!(self.current_page, Page::ShowImage) && previewed == Some(index) {
+                return self.crop_action(action);
             }
-            KeyCode::Down => {
-                self.selected = self.get_bottom();
-                if self.show_selections {
-                    self.selected = self.selected.min(self.selections_list.len() - 1)
-                }
-                if let Page::Gallery = self.current_page {
-                    return self.update_scroll_offset();
-                }
+            self.crop = None;
+        }
+        match self.current_page {
+            Page::Welcome | Page::Error(_) => return Command::none(),
+            Page::Compare => return self.compare_action(action),
+            _ => (),
+        }
+        match action {
+            Action::ZoomIn => {
+                self.zoom(ZOOM_STEP);
+                return self.update_scroll_offset();
+            }
+            Action::ZoomOut => {
+                self.zoom(-ZOOM_STEP);
+                return self.update_scroll_offset();
+            }
+            Action::Previous | Action::Next | Action::Up | Action::Down => {
+                return self.move_selection(action, 1);
             }
-            KeyCode::Equals | KeyCode::Key0 => {
-                self.img_width = self.container_dim.0 / 5;
+            Action::ZoomReset => {
+                self.img_width = (self.container_dim.0 / self.config.layout.columns.max(1)).max(1);
                 return self.update_scroll_offset();
             }
-            KeyCode::Enter => match self.current_page {
+            Action::Open => match self.current_page {
                 Page::Gallery => {
                     self.current_page = Page::ShowImage;
                     return self.update_preview_data();
@@ -301,27 +1739,82 @@ impl Fuu {
                 }
                 _ => (),
             },
-            KeyCode::M => {
-                let index = if self.show_selections {
-                    self.selections_list[self.selected]
-                } else {
-                    self.selected
-                };
-                if !self.selections_list.insert(index) {
-                    self.selections_list.remove(&index);
+            Action::Mark if self.selected < self.view_len() => {
+                self.toggle_mark(self.image_index(self.selected));
+            }
+            Action::ToggleColorManagement => {
+                self.toggle_color_management();
+                if let Page::ShowImage | Page::Compare = self.current_page {
+                    return self.update_preview_data();
+                }
+            }
+            Action::Compare => return self.open_compare(),
+            Action::RotateRight => return self.transform_images(Transform::RotateRight),
+            Action::RotateLeft => return self.transform_images(Transform::RotateLeft),
+            Action::FlipHorizontal => return self.transform_images(Transform::FlipHorizontal),
+            Action::FlipVertical => return self.transform_images(Transform::FlipVertical),
+            Action::Convert if self.selections_list.is_empty() => {
+                self.notice = Some(String::from("mark the images to convert"));
+            }
+            Action::Convert => return self.open_prompt(PromptKind::Convert),
+            Action::ContactSheet => return self.write_contact_sheet(),
+            Action::RunCommand(index) => return self.run_external(index),
+            Action::Crop => {
+                if let Page::ShowImage = self.current_page {
+                    return self.start_crop();
+                }
+            }
+            Action::ToggleHistogram => {
+                if let Page::ShowImage = self.current_page {
+                    self.show_histogram ^= true;
+                    return self.update_preview_data();
                 }
             }
-            KeyCode::Space => {
+            Action::ToggleSelections => {
                 if let Page::Gallery = self.current_page {
                     self.show_selections ^= true;
-                    self.selected = 0;
+                    self.show_duplicates = false;
+                    self.refresh_view();
+                    return self.update_scroll_offset();
+                }
+            }
+            Action::Rate(rating) => {
+                return self.update_metadata(|metadata| metadata.rating = rating.min(5));
+            }
+            Action::Label(label) => {
+                return self.update_metadata(|metadata| {
+                    metadata.label = (metadata.label != Some(label)).then_some(label);
+                });
+            }
+            Action::FilterRating(rating) => {
+                self.min_rating = rating.min(5);
+                self.refresh_view();
+                return self.reveal_selected();
+            }
+            Action::ToggleDuplicates => {
+                if let Page::Gallery = self.current_page {
+                    self.show_duplicates ^= true;
+                    self.show_selections = false;
+                    self.duplicate_groups.clear();
+                    self.refresh_view();
+                    if self.show_duplicates {
+                        return self.find_duplicates();
+                    }
+                    return self.update_scroll_offset();
                 }
             }
-            KeyCode::Escape => {
+            Action::MarkDuplicates if self.show_duplicates => self.mark_duplicates(),
+            Action::EditTags if self.selected < self.view_len() => {
+                return self.open_prompt(PromptKind::Tags);
+            }
+            Action::FilterTags => return self.open_prompt(PromptKind::Filter),
+            Action::Back => {
                 match self.current_page {
-                    Page::Gallery => if self.show_selections {
+                    Page::Gallery => if self.show_selections || self.show_duplicates {
                         self.show_selections = false;
-                        self.selected = self.selections_list[self.selected];
+                        self.show_duplicates = false;
+                        self.refresh_view();
+                        return self.update_scroll_offset();
                     }
                     Page::ShowImage => {
                         self.current_page = Page::Gallery;
@@ -335,8 +1828,27 @@ impl Fuu {
     }
     
     pub fn update(&mut self, message: Message) -> Command<Message> {
+        if !matches!(message, Message::Scrolled(_)) {
+            self.card_bounds_cache.get_mut().take();
+        }
         match message {
             Message::CloseRequested => {
+                if self.writing_files() {
+                    self.quitting = true;
+                    if let Some(conversion) = &mut self.conversion {
+                        conversion.cancel();
+                    }
+                    self.notice = Some(String::from("quitting once the files are written"));
+                    return Command::none();
+                }
+                if let Err(err) = self.window_geometry.save() {
+                    eprintln!("cannot save window geometry: {err}");
+                }
+                if self.config.session.save && !self.images.is_empty() {
+                    if let Err(err) = self.session().save() {
+                        eprintln!("cannot save session: {err}");
+                    }
+                }
                 let mut stdout = io::stdout().lock();
                 for index in &self.selections_list {
                     let source_path = self.images[*index].preview.as_path();
@@ -346,10 +1858,18 @@ impl Fuu {
                 }
                 std::process::exit(0)
             }
-            Message::KeyPress(key) => return self.handle_keypress(key),
+            Message::KeyPress(key, modifiers) => return self.handle_keypress(key, modifiers),
+            Message::Action(action) => return self.perform_action(action),
+            Message::WindowMoved { x, y } if !self.fullscreen => {
+                self.window_geometry.position = Some((x, y));
+            }
             Message::WindowResize { width, height } => {
-                self.container_dim = (width, height);
-                self.img_width = width / 5;
+                if !self.fullscreen {
+                    self.window_geometry.width = width;
+                    self.window_geometry.height = height;
+                }
+                self.container_dim = (width, height.saturating_sub(style::STATUS_BAR_HEIGHT));
+                self.img_width = (width / self.config.layout.columns.max(1)).max(1);
                 return self.update_scroll_offset();
             }
             Message::ChangeFocus(selected) => {
@@ -361,60 +1881,78 @@ impl Fuu {
                 return self.update_scroll_offset();
             }
             Message::SourcesLoaded(sources) => {
-                let mut image_cards = IndexSet::with_capacity(self.images.len());
-                image_cards.extend(
-                    self.images
-                        .drain(..)
-                        .chain(sources.into_iter().map(ImageCard::new)),
-                );
-                self.images = image_cards.into_iter().collect();
+                let added = self.add_images(sources);
+                let mut shown: HashSet<usize> = self.order.iter().copied().collect();
+                for index in added {
+                    if shown.insert(index) {
+                        self.order.push(index);
+                    }
+                }
+                self.refresh_view();
                 if !self.images.is_empty() {
                     self.current_page = Page::Gallery;
-                    return Command::perform(async {}, |_| Message::LoadThumbs);
+                    let load_thumbs = Command::batch([
+                        Command::perform(async {}, |_| Message::LoadThumbs),
+                        self.load_metadata(),
+                        self.hash_images(),
+                    ]);
+                    if let Some(session) = self.pending_session.take() {
+                        return Command::batch([self.restore_session(session), load_thumbs]);
+                    }
+                    return load_thumbs;
                 }
                 self.current_page = Page::Welcome;
             }
-            Message::LoadThumbs => {
-                return Command::batch(
-                    self.images
-                        .iter()
-                        .enumerate()
-                        .filter(|(_, image_card)| {
-                            matches!(image_card.thumb_state, ThumbState::Loading)
-                        })
-                        .take(COMMANDS_NUM)
-                        .map(|(i, image_card)| {
-                            Command::perform(generate_thumb(image_card.clone()), move |dim| {
-                                Message::ThumbLoaded(dim, i)
-                            })
-                        }),
-                )
-            }
-            Message::ThumbLoaded(Some(dim), index) => {
+            Message::LoadThumbs => return self.schedule_thumbs(),
+            Message::Scrolled(viewport) => {
+                self.current_scroll_offset = viewport.absolute_offset();
+                return self.schedule_thumbs();
+            }
+            Message::ThumbLoaded(info, index) => {
+                self.thumb_scheduler.finish(index);
                 let image_card = &mut self.images[index];
-                (image_card.width, image_card.height) = dim;
-                image_card.thumb_state = ThumbState::Loaded;
-                let counter = COMMAND_COUNTER.load(Ordering::Relaxed) + 1;
-                COMMAND_COUNTER.store(counter, Ordering::Relaxed);
-                if counter == COMMANDS_NUM {
-                    COMMAND_COUNTER.store(0, Ordering::Relaxed);
-                    return Command::perform(async {}, |_| Message::LoadThumbs);
+                match info {
+                    Some(info) => {
+                        (image_card.width, image_card.height) = (info.width, info.height);
+                        image_card.source_dim = info.source_dim;
+                        image_card.dhash = info.dhash;
+                        image_card.thumb_state = ThumbState::Loaded;
+                    }
+                    None => image_card.thumb_state = ThumbState::Error,
                 }
-            }
-            Message::ThumbLoaded(None, index) => {
-                self.images[index].thumb_state = ThumbState::Error;
-            }
-            Message::PreviewLoaded(Some(rgba_image), index) => {
-                if self.selected == index || self.selections_list.contains(&index) {
-                    self.images[index].preview_state = ImageState::Loaded(rgba_image);
+                let command = self.schedule_thumbs();
+                // group again once every hash is known
+                if self.show_duplicates && self.thumb_scheduler.is_idle() {
+                    return Command::batch([command, self.find_duplicates()]);
                 }
+                return command;
             }
-            Message::PreviewLoaded(None, index) => {
-                self.images[index].preview_state = ImageState::Error;
+            Message::PreviewLoaded(preview, index, unmanaged) => {
+                self.preview_requests.remove(&index);
+                let window = self.preview_window();
+                if !window.contains(&index) {
+                    return Command::none();
+                }
+                // color management was toggled while it was loading
+                if unmanaged != self.show_unmanaged {
+                    return self.fetch_preview(index);
+                }
+                match preview {
+                    Some(preview) => {
+                        self.images[index].preview_state = ImageState::Loaded(preview);
+                        self.touch_preview(index);
+                        self.evict_previews();
+                    }
+                    None => self.images[index].preview_state = ImageState::Error,
+                }
+                if let Page::ShowImage | Page::Compare = self.current_page {
+                    return self.update_preview_data();
+                }
             }
             Message::FileDropped(file_path) => {
                 self.file_drag = false;
                 let sources = ImageSource::Path(file_path);
+                self.sources.push(sources.key());
                 return Command::perform(read_sources(vec![sources]), Message::SourcesLoaded);
             }
             Message::FileHovered => {
@@ -422,9 +1960,225 @@ impl Fuu {
             }
             Message::HideOverlay => {
                 self.file_drag = false;
+                self.context_menu = None;
+            }
+            Message::WheelZoom(zoom_in, cursor) => {
+                if let Page::Gallery = self.current_page {
+                    return self.zoom_at(zoom_in, cursor);
+                }
+            }
+            Message::ContextMenu(position) => {
+                let cursor = *super::POINTER.lock().unwrap();
+                self.context_menu = Some((position, cursor));
+            }
+            Message::CardAction(action, position) => return self.card_action(action, position),
+            Message::DuplicatesGrouped(groups) => {
+                self.grouping_duplicates = false;
+                if !self.show_duplicates {
+                    self.regroup_duplicates = false;
+                    return Command::none();
+                }
+                let first = self.duplicate_groups.is_empty();
+                self.duplicate_groups = groups;
+                self.refresh_view();
+                let regroup = match std::mem::take(&mut self.regroup_duplicates) {
+                    true => self.find_duplicates(),
+                    false => Command::none(),
+                };
+                if first {
+                    return Command::batch([regroup, self.update_scroll_offset()]);
+                }
+                return regroup;
+            }
+            Message::MetadataLoaded(metadata) => {
+                for (index, metadata) in metadata {
+                    self.metadata_requests.remove(&index);
+                    let image_card = &mut self.images[index];
+                    if !image_card.metadata_loaded {
+                        image_card.metadata = metadata;
+                        image_card.metadata_loaded = true;
+                    }
+                }
+                if self.min_rating > 0 {
+                    self.refresh_view();
+                    return self.reveal_selected();
+                }
+            }
+            Message::HashesLoaded(hashes) => {
+                let mut commands = Vec::new();
+                for (index, hash) in hashes {
+                    // failed images are hashed again the next time hashes are needed
+                    self.hash_requests.remove(&index);
+                    let edit = self.pending_tag_edits.remove(&index);
+                    match (hash, edit) {
+                        (Ok(hash), edit) => {
+                            self.images[index].hash = Some(hash);
+                            if let Some(edit) = edit {
+                                commands.push(self.apply_tag_edit(index, &edit));
+                            }
+                        }
+                        (Err(err), Some(_)) => self.notice = Some(err),
+                        (Err(_), None) => (),
+                    }
+                }
+                if !commands.is_empty() {
+                    return Command::batch(commands);
+                }
+                if self.tag_query.is_some() {
+                    self.refresh_view();
+                    return self.reveal_selected();
+                }
+            }
+            Message::PromptChanged(value) => {
+                if let Some(prompt) = &mut self.prompt {
+                    prompt.value = value;
+                }
+            }
+            Message::PromptSubmitted => return self.submit_prompt(),
+            Message::CompareTransform(transform) => self.compare_transform = transform,
+            Message::DiffComputed(pair, result) => {
+                if self.diff_request == Some(pair) {
+                    self.diff_request = None;
+                }
+                if let Err(err) = &result {
+                    self.notice = Some(err.clone());
+                }
+                self.diff = Some((pair, result));
+                self.onion = None;
+                return self.update_diff();
+            }
+            Message::OnionOpacity(opacity) => return self.set_onion_opacity(opacity),
+            Message::Converted(result) => {
+                let Some(conversion) = &mut self.conversion else {
+                    return Command::none();
+                };
+                if let Err(err) = &result {
+                    eprintln!("{err}");
+                }
+                conversion.finish(result);
+                if !conversion.is_done() {
+                    return self.convert_next();
+                }
+                let mut notice = format!("{conversion} to {}", conversion.options.dir.display());
+                if let Some(err) = conversion.errors.first() {
+                    notice += &format!(": {err}");
+                }
+                self.notice = Some(notice);
+                self.conversion = None;
+                return self.quit_when_written();
+            }
+            Message::CommandFinished(index, affected, result) => {
+                if let Some(position) = self.running_commands.iter().position(|&i| i == index) {
+                    self.running_commands.remove(position);
+                }
+                let Some(command) = self.config.commands.get(index) else {
+                    return Command::none();
+                };
+                let reload = command.reload && result.is_ok();
+                self.notice = Some(match result {
+                    Ok(output) if output.status.success() => format!("{} finished", command.name),
+                    Ok(output) => {
+                        eprint!("{}", output.stderr);
+                        let mut notice = format!("{} failed with {}", command.name, output.status);
+                        if let Some(line) = output.last_error() {
+                            notice += &format!(": {line}");
+                        }
+                        notice
+                    }
+                    Err(err) => err,
+                });
+                if reload {
+                    // the gallery may have changed while the program ran
+                    let images: Vec<usize> =
+                        affected.iter().filter_map(|path| self.find_image(path)).collect();
+                    let mut commands: Vec<_> =
+                        images.into_iter().map(|index| self.invalidate(index)).collect();
+                    commands.extend([self.schedule_thumbs(), self.hash_images()]);
+                    return Command::batch(commands);
+                }
+            }
+            Message::ContactSheetWritten(result) => {
+                self.writing_contact_sheet = false;
+                self.notice = Some(match result {
+                    Ok(pages) => self.contact_sheet_notice(&pages),
+                    Err(err) => err,
+                });
+                return self.quit_when_written();
+            }
+            Message::Transformed(path, result) => {
+                let command = self.transformed(path, result);
+                return Command::batch([command, self.quit_when_written()]);
+            }
+            Message::CropChanged(rect) => {
+                if let Some((_, current)) = &mut self.crop {
+                    *current = rect;
+                }
+            }
+            Message::CropExported(Ok(path), to_clipboard) => {
+                self.notice = Some(format!("cropped to {}", path.display()));
+                self.crop = None;
+                if to_clipboard {
+                    return clipboard::write(path.display().to_string());
+                }
+            }
+            Message::CropExported(Err(err), _) => self.notice = Some(err),
+            Message::HistogramComputed(index, histogram) => {
+                if self.histogram_request == Some(index) {
+                    self.histogram_request = None;
+                }
+                // navigation may have moved on while it was computed
+                let previewed = self.previewed_image();
+                if matches!(self.current_page, Page::ShowImage) && previewed == Some(index) {
+                    self.histogram = histogram.map(|histogram| (index, histogram));
+                }
+            }
+            // blends made for an opacity the slider already left are dropped
+            Message::OnionBlended(opacity, Some(preview)) if opacity == self.onion_opacity => {
+                self.onion = Some(preview);
+            }
+            Message::FileChanged(change) => return self.file_changed(change),
+            Message::FilesAdded(sources) => {
+                let mut commands = Vec::new();
+                for index in self.add_images(sources) {
+                    if self.order.contains(&index) {
+                        commands.push(self.invalidate(index));
+                    } else {
+                        self.insert_in_order(index);
+                    }
+                }
+                self.refresh_view();
+                commands.extend([self.schedule_thumbs(), self.load_metadata(), self.hash_images()]);
+                return Command::batch(commands);
             }
             _ => (),
         }
         Command::none()
     }
 }
+
+fn tag_list(tags: &Tags) -> String {
+    tags.iter()
+        .map(|tag| format!("#{tag}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn stars(rating: u8) -> String {
+    "\u{2605}".repeat(rating as usize)
+}
+
+/// Rating and color label drawn under a thumbnail
+fn badges<'a>(metadata: &Metadata, tags: String) -> Element<'a, Message> {
+    let mut badges = row![text(stars(metadata.rating)).size(12)].spacing(4);
+    if let Some(label) = metadata.label {
+        let [r, g, b] = label.rgb();
+        badges = badges.push(text("\u{25CF}").size(12).style(iced::Color::from_rgb(r, g, b)));
+    }
+    if !tags.is_empty() {
+        badges = badges.push(text(tags).size(12));
+    }
+    container(badges)
+        .height(style::BADGE_HEIGHT as u16)
+        .center_y()
+        .into()
+}
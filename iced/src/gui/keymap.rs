@@ -0,0 +1,491 @@
+use crate::config::{CommandConfig, KeyConfig};
+use crate::xmp::ColorLabel;
+use iced::keyboard::{KeyCode, Modifiers};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Everything the user can do from the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Next,
+    Previous,
+    Up,
+    Down,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    Open,
+    Mark,
+    ToggleSelections,
+    ToggleColorManagement,
+    ToggleFullscreen,
+    /// Give the selected image from 0 to 5 stars
+    Rate(u8),
+    /// Set the color label of the selected image, or remove it when it's the same
+    Label(ColorLabel),
+    /// Only show images rated at least that many stars
+    FilterRating(u8),
+    /// Show the marked images side by side
+    Compare,
+    /// Switch the compare page between side by side, heatmap and onion skin
+    ToggleDiff,
+    /// Show the histograms and channel statistics of the previewed image
+    ToggleHistogram,
+    /// Draw a rectangle over the previewed image to export it to a new file
+    Crop,
+    /// Switch the crop rectangle to the next aspect ratio preset
+    CropAspect,
+    /// Export the crop to the clipboard directory instead of next to the original
+    CropToClipboard,
+    /// Convert and resize the marked images into new files
+    Convert,
+    /// Render the marked images, or the whole gallery, into contact sheet pages
+    ContactSheet,
+    /// Rotate or flip the marked images, or the selected one, and save them
+    RotateRight,
+    RotateLeft,
+    FlipHorizontal,
+    FlipVertical,
+    /// Show the groups of images that look alike
+    ToggleDuplicates,
+    /// Mark every image of the duplicate groups but the largest one
+    MarkDuplicates,
+    /// Type tags for the selected image
+    EditTags,
+    /// Type a tag query the gallery is filtered with
+    FilterTags,
+    Back,
+    Quit,
+    /// Run the external program of the `[[commands]]` entry at this index
+    RunCommand(usize),
+}
+
+const RATE_NAMES: [&str; 6] = ["rate-0", "rate-1", "rate-2", "rate-3", "rate-4", "rate-5"];
+const FILTER_NAMES: [&str; 6] = [
+    "filter-rating-0",
+    "filter-rating-1",
+    "filter-rating-2",
+    "filter-rating-3",
+    "filter-rating-4",
+    "filter-rating-5",
+];
+const RATE_KEYS: [&[&str]; 6] = [&["0"], &["1"], &["2"], &["3"], &["4"], &["5"]];
+const FILTER_KEYS: [&[&str]; 6] = [
+    &["ctrl+0"],
+    &["ctrl+1"],
+    &["ctrl+2"],
+    &["ctrl+3"],
+    &["ctrl+4"],
+    &["ctrl+5"],
+];
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::Next,
+        Action::Previous,
+        Action::Up,
+        Action::Down,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ZoomReset,
+        Action::Open,
+        Action::Mark,
+        Action::ToggleSelections,
+        Action::ToggleColorManagement,
+        Action::ToggleFullscreen,
+        Action::Rate(0),
+        Action::Rate(1),
+        Action::Rate(2),
+        Action::Rate(3),
+        Action::Rate(4),
+        Action::Rate(5),
+        Action::Label(ColorLabel::Red),
+        Action::Label(ColorLabel::Yellow),
+        Action::Label(ColorLabel::Green),
+        Action::Label(ColorLabel::Blue),
+        Action::Label(ColorLabel::Purple),
+        Action::FilterRating(0),
+        Action::FilterRating(1),
+        Action::FilterRating(2),
+        Action::FilterRating(3),
+        Action::FilterRating(4),
+        Action::FilterRating(5),
+        Action::EditTags,
+        Action::FilterTags,
+        Action::ToggleDuplicates,
+        Action::MarkDuplicates,
+        Action::Compare,
+        Action::ToggleDiff,
+        Action::ToggleHistogram,
+        Action::Crop,
+        Action::CropAspect,
+        Action::CropToClipboard,
+        Action::Convert,
+        Action::ContactSheet,
+        Action::RotateRight,
+        Action::RotateLeft,
+        Action::FlipHorizontal,
+        Action::FlipVertical,
+        Action::Back,
+        Action::Quit,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Next => "next",
+            Action::Previous => "previous",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::ZoomIn => "zoom-in",
+            Action::ZoomOut => "zoom-out",
+            Action::ZoomReset => "zoom-reset",
+            Action::Open => "open",
+            Action::Mark => "mark",
+            Action::ToggleSelections => "toggle-selections",
+            Action::ToggleColorManagement => "toggle-color-management",
+            Action::ToggleFullscreen => "toggle-fullscreen",
+            Action::Rate(rating) => RATE_NAMES[(*rating).min(5) as usize],
+            Action::Label(ColorLabel::Red) => "label-red",
+            Action::Label(ColorLabel::Yellow) => "label-yellow",
+            Action::Label(ColorLabel::Green) => "label-green",
+            Action::Label(ColorLabel::Blue) => "label-blue",
+            Action::Label(ColorLabel::Purple) => "label-purple",
+            Action::FilterRating(rating) => FILTER_NAMES[(*rating).min(5) as usize],
+            Action::EditTags => "edit-tags",
+            Action::FilterTags => "filter-tags",
+            Action::ToggleDuplicates => "toggle-duplicates",
+            Action::MarkDuplicates => "mark-duplicates",
+            Action::Compare => "compare",
+            Action::ToggleDiff => "toggle-diff",
+            Action::ToggleHistogram => "toggle-histogram",
+            Action::Crop => "crop",
+            Action::CropAspect => "crop-aspect",
+            Action::CropToClipboard => "crop-to-clipboard",
+            Action::Convert => "convert",
+            Action::ContactSheet => "contact-sheet",
+            Action::RotateRight => "rotate-right",
+            Action::RotateLeft => "rotate-left",
+            Action::FlipHorizontal => "flip-horizontal",
+            Action::FlipVertical => "flip-vertical",
+            Action::Back => "back",
+            Action::Quit => "quit",
+            Action::RunCommand(_) => "run-command",
+        }
+    }
+
+    fn default_keys(&self) -> &'static [&'static str] {
+        match self {
+            Action::Next => &["right", "n"],
+            Action::Previous => &["left", "p"],
+            Action::Up => &["up"],
+            Action::Down => &["down"],
+            Action::ZoomIn => &["plus", "numpad-add"],
+            Action::ZoomOut => &["minus", "numpad-subtract"],
+            Action::ZoomReset => &["equals"],
+            Action::Open => &["enter"],
+            Action::Mark => &["m"],
+            Action::ToggleSelections => &["space"],
+            Action::ToggleColorManagement => &["c"],
+            Action::ToggleFullscreen => &["f", "f11"],
+            Action::Rate(rating) => RATE_KEYS[(*rating).min(5) as usize],
+            Action::Label(ColorLabel::Red) => &["6"],
+            Action::Label(ColorLabel::Yellow) => &["7"],
+            Action::Label(ColorLabel::Green) => &["8"],
+            Action::Label(ColorLabel::Blue) => &["9"],
+            Action::Label(ColorLabel::Purple) => &[],
+            Action::FilterRating(rating) => FILTER_KEYS[(*rating).min(5) as usize],
+            Action::EditTags => &["t"],
+            Action::FilterTags => &["slash"],
+            Action::ToggleDuplicates => &["d"],
+            Action::MarkDuplicates => &["shift+d"],
+            Action::Compare => &["shift+c"],
+            Action::ToggleDiff => &["x"],
+            Action::ToggleHistogram => &["shift+h"],
+            Action::Crop => &["shift+x"],
+            Action::CropAspect => &["a"],
+            Action::CropToClipboard => &["y"],
+            Action::Convert => &["shift+e"],
+            Action::ContactSheet => &["shift+s"],
+            Action::RotateRight => &["r"],
+            Action::RotateLeft => &["shift+r"],
+            Action::FlipHorizontal => &["shift+f"],
+            Action::FlipVertical => &["shift+v"],
+            Action::Back => &["escape"],
+            Action::Quit => &["q"],
+            // bound by the `keys` of each command
+            Action::RunCommand(_) => &[],
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .iter()
+            .find(|action| action.name() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown action `{name}`"))
+    }
+}
+
+/// A key with the modifiers that must be held down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    pub key: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl KeyBinding {
+    pub fn new(key: KeyCode, modifiers: Modifiers) -> Self {
+        let relevant = Modifiers::SHIFT | Modifiers::CTRL | Modifiers::ALT | Modifiers::LOGO;
+        Self {
+            key,
+            modifiers: modifiers & relevant,
+        }
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    /// Parse bindings like `ctrl+shift+n`
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let lowercase = input.trim().to_lowercase();
+        // the last part is the key itself so `ctrl++` binds the plus key
+        let (prefix, key) = match lowercase.strip_suffix('+') {
+            Some(rest) if rest.is_empty() || rest.ends_with('+') => (rest, "+"),
+            _ => lowercase.rsplit_once('+').unwrap_or(("", &lowercase)),
+        };
+        let mut modifiers = Modifiers::empty();
+        for modifier in prefix.split('+').filter(|modifier| !modifier.is_empty()) {
+            modifiers |= match modifier {
+                "ctrl" | "control" => Modifiers::CTRL,
+                "alt" => Modifiers::ALT,
+                "shift" => Modifiers::SHIFT,
+                "super" | "logo" | "cmd" => Modifiers::LOGO,
+                _ => return Err(format!("unknown modifier `{modifier}` in `{input}`")),
+            };
+        }
+        let key = parse_key(key).ok_or_else(|| format!("unknown key `{key}` in `{input}`"))?;
+        Ok(Self::new(key, modifiers))
+    }
+}
+
+/// Whether vim mode takes the key for the start of a count
+fn is_count(binding: KeyBinding) -> bool {
+    const COUNT_DIGITS: [KeyCode; 18] = [
+        KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5,
+        KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9, KeyCode::Numpad1,
+        KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4, KeyCode::Numpad5,
+        KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+    ];
+    binding.modifiers.is_empty() && COUNT_DIGITS.contains(&binding.key)
+}
+
+fn parse_key(name: &str) -> Option<KeyCode> {
+    const LETTERS: [KeyCode; 26] = [
+        KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+        KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+        KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+        KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    ];
+    const DIGITS: [KeyCode; 10] = [
+        KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+        KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    ];
+    const FUNCTIONS: [KeyCode; 12] = [
+        KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+        KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    ];
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_lowercase() {
+            return Some(LETTERS[(c as u8 - b'a') as usize]);
+        }
+        if c.is_ascii_digit() {
+            return Some(DIGITS[(c as u8 - b'0') as usize]);
+        }
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<usize>().ok()) {
+        return FUNCTIONS.get(n.checked_sub(1)?).copied();
+    }
+    let key = match name {
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "enter" | "return" => KeyCode::Enter,
+        "space" => KeyCode::Space,
+        "escape" | "esc" => KeyCode::Escape,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" | "page-up" => KeyCode::PageUp,
+        "pagedown" | "page-down" => KeyCode::PageDown,
+        "plus" | "+" => KeyCode::Plus,
+        "minus" | "-" => KeyCode::Minus,
+        "equals" | "=" => KeyCode::Equals,
+        "comma" | "," => KeyCode::Comma,
+        "period" | "." => KeyCode::Period,
+        "slash" | "/" => KeyCode::Slash,
+        "backslash" | "\\" => KeyCode::Backslash,
+        "semicolon" | ";" => KeyCode::Semicolon,
+        "apostrophe" | "'" => KeyCode::Apostrophe,
+        "grave" | "`" => KeyCode::Grave,
+        "lbracket" | "[" => KeyCode::LBracket,
+        "rbracket" | "]" => KeyCode::RBracket,
+        "numpad-add" => KeyCode::NumpadAdd,
+        "numpad-subtract" => KeyCode::NumpadSubtract,
+        "numpad-enter" => KeyCode::NumpadEnter,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    bindings: HashMap<KeyBinding, Action>,
+}
+
+impl Keymap {
+    /// Build the default keymap with the user overrides, an action listed in
+    /// the config loses its default keys, and the keys of the commands must
+    /// be free. Every invalid entry is reported. In `vim` mode the digits from
+    /// 1 to 9 start counts, so the default ones are held with alt
+    pub fn new(
+        config: &KeyConfig,
+        commands: &[CommandConfig],
+        vim: bool,
+    ) -> Result<Self, Vec<String>> {
+        let mut errors = Vec::new();
+        let mut overrides = HashMap::new();
+        for (name, keys) in config {
+            match name.parse::<Action>() {
+                Ok(action) => {
+                    overrides.insert(action, keys.as_slice());
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+        let mut bindings = HashMap::new();
+        for action in Action::ALL {
+            if overrides.contains_key(action) {
+                continue;
+            }
+            for key in action.default_keys() {
+                let mut binding: KeyBinding = key.parse().expect("invalid default key binding");
+                if vim && is_count(binding) {
+                    binding.modifiers = Modifiers::ALT;
+                }
+                bindings.insert(binding, *action);
+            }
+        }
+        for (action, keys) in overrides {
+            for key in keys {
+                match key.parse() {
+                    Ok(binding) => {
+                        bindings.insert(binding, action);
+                    }
+                    Err(err) => errors.push(format!("{err} for action `{action}`")),
+                }
+            }
+        }
+        for (index, command) in commands.iter().enumerate() {
+            for key in command.keys.as_slice() {
+                match key.parse() {
+                    Ok(binding) => match bindings.insert(binding, Action::RunCommand(index)) {
+                        None => (),
+                        Some(Action::RunCommand(other)) => errors.push(format!(
+                            "`{key}` of command `{}` is already bound to command `{}`",
+                            command.name, commands[other].name
+                        )),
+                        Some(action) => errors.push(format!(
+                            "`{key}` of command `{}` is already bound to `{action}`, \
+                             give `{action}` other keys in [keys] to use it",
+                            command.name
+                        )),
+                    },
+                    Err(err) => errors.push(format!("{err} for command `{}`", command.name)),
+                }
+            }
+        }
+        if vim {
+            for (binding, action) in &bindings {
+                if is_count(*binding) {
+                    eprintln!("{action} is bound to a digit, vim mode reads it as a count");
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(Self { bindings })
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn action(&self, key: KeyCode, modifiers: Modifiers) -> Option<Action> {
+        let binding = KeyBinding::new(key, modifiers);
+        self.bindings.get(&binding).copied().or_else(|| {
+            // keys like `+` are typed with shift on most layouts
+            let unshifted = KeyBinding::new(key, binding.modifiers - Modifiers::SHIFT);
+            self.bindings.get(&unshifted).copied()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Keys;
+
+    fn command(name: &str, key: &str) -> CommandConfig {
+        CommandConfig {
+            name: String::from(name),
+            command: vec![String::from("true")],
+            keys: Keys::One(String::from(key)),
+            reload: false,
+        }
+    }
+
+    #[test]
+    fn command_keys_cannot_take_a_default_binding() {
+        let errors = Keymap::new(&KeyConfig::new(), &[command("gimp", "r")], false).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("`rotate-right`"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn command_keys_cannot_take_an_overridden_binding() {
+        let config = KeyConfig::from([(String::from("quit"), Keys::One(String::from("ctrl+g")))]);
+        let errors = Keymap::new(&config, &[command("gimp", "ctrl+g")], false).unwrap_err();
+        assert!(errors[0].contains("`quit`"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn command_keys_cannot_be_shared() {
+        let commands = [command("gimp", "ctrl+g"), command("krita", "ctrl+g")];
+        let errors = Keymap::new(&KeyConfig::new(), &commands, false).unwrap_err();
+        assert!(errors[0].contains("command `gimp`"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn freed_keys_can_be_given_to_commands() {
+        let rotate_right = (String::from("rotate-right"), Keys::One(String::from("ctrl+r")));
+        let config = KeyConfig::from([rotate_right]);
+        let keymap = Keymap::new(&config, &[command("gimp", "r")], false).unwrap();
+        assert_eq!(keymap.action(KeyCode::R, Modifiers::empty()), Some(Action::RunCommand(0)));
+        assert_eq!(keymap.action(KeyCode::R, Modifiers::CTRL), Some(Action::RotateRight));
+    }
+}
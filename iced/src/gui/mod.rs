@@ -1,33 +1,93 @@
 pub mod components;
+pub mod duplicates;
 pub mod fuu;
+pub mod keymap;
+pub mod scheduler;
 pub mod style;
 pub mod types;
+pub mod vim;
+pub mod watcher;
 pub mod widgets;
 
+use crate::color::ColorManagement;
+use crate::config::{Config, ThemeName};
+use crate::geometry::WindowGeometry;
+use crate::open_with::CommandOutput;
+use crate::session::Session;
+use crate::tags::TagDb;
+use crate::xmp;
 use crate::gui::components::{error_view, welcome_page};
 use crate::gui::widgets::modal::Modal;
 use crate::utils::*;
 use fuu::Fuu;
+use keymap::Keymap;
 use iced::font;
 use iced::keyboard::{self, KeyCode};
-use iced::widget::{container, text};
-use iced::{executor, window, Application, Command, Element, Event, Subscription, Theme};
+use iced::widget::{column, container, scrollable, text};
+use iced::mouse;
+use iced::{executor, window, Application, Command, Element, Event, Point, Subscription, Theme};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
 use std::path::PathBuf;
 use types::*;
 
+pub struct Flags {
+    pub sources: Vec<ImageSource>,
+    /// The user config, or the reason it couldn't be loaded
+    pub config: Result<Config, String>,
+    pub geometry: WindowGeometry,
+    pub fullscreen: bool,
+    pub resume: bool,
+}
+
+/// Cursor position, tracked outside of `Fuu` so moving the mouse doesn't rebuild the view
+static POINTER: Lazy<Mutex<Point>> = Lazy::new(Default::default);
+
 #[derive(Debug, Clone)]
 pub enum Message {
     WindowResize { width: u32, height: u32 },
-    KeyPress(KeyCode),
+    WindowMoved { x: i32, y: i32 },
+    KeyPress(KeyCode, keyboard::Modifiers),
+    Action(keymap::Action),
     ChangeFocus(usize),
     FontLoaded(Result<(), font::Error>),
     SourcesLoaded(Vec<ImageSource>),
-    ThumbLoaded(Option<(u32, u32)>, usize),
-    PreviewLoaded(Option<bytes::Bytes>, usize),
+    ThumbLoaded(Option<ThumbInfo>, usize),
+    /// A preview, and whether it was made without color management
+    PreviewLoaded(Option<Preview>, usize, bool),
     FileDropped(PathBuf),
     FileHovered,
     HideOverlay,
     LoadThumbs,
+    Scrolled(scrollable::Viewport),
+    /// Ctrl + mouse wheel, zooming in when true
+    WheelZoom(bool, Point),
+    ContextMenu(usize),
+    CardAction(CardAction, usize),
+    HashesLoaded(Vec<(usize, Result<String, String>)>),
+    /// Groups of images that look alike, the largest first
+    DuplicatesGrouped(Vec<Vec<usize>>),
+    /// Ratings and labels read from the sidecars
+    MetadataLoaded(Vec<(usize, xmp::Metadata)>),
+    PromptChanged(String),
+    PromptSubmitted,
+    CompareTransform(widgets::synced_viewer::ViewTransform),
+    DiffComputed((usize, usize), Result<DiffPreview, String>),
+    OnionOpacity(f32),
+    /// A blended preview and the opacity it was made with
+    OnionBlended(f32, Option<Preview>),
+    HistogramComputed(usize, Option<HistogramPreview>),
+    CropChanged(crate::crop::CropRect),
+    /// Where the crop was written, and whether its path goes to the clipboard
+    CropExported(Result<PathBuf, String>, bool),
+    Converted(Result<PathBuf, String>),
+    ContactSheetWritten(Result<crate::contact_sheet::Pages, String>),
+    /// A command of the config exited, with the files it was given to reload
+    CommandFinished(usize, Vec<PathBuf>, Result<CommandOutput, String>),
+    Transformed(PathBuf, Result<(String, String), String>),
+    FileChanged(watcher::FileChange),
+    /// Images that appeared in a watched directory
+    FilesAdded(Vec<ImageSource>),
     CloseRequested,
 }
 
@@ -35,20 +95,73 @@ impl Application for Fuu {
     type Executor = executor::Default;
     type Message = Message;
     type Theme = iced::Theme;
-    type Flags = Vec<ImageSource>;
+    type Flags = Flags;
 
     fn new(flags: Self::Flags) -> (Self, Command<Message>) {
+        let fonts = Command::batch([
+            font::load(include_bytes!("../../fonts/icons-subset.ttf").as_slice())
+                .map(Message::FontLoaded),
+            font::load(include_bytes!("../../fonts/japanese-subset.ttf").as_slice())
+                .map(Message::FontLoaded),
+        ]);
+        let startup = flags.config.and_then(|config| {
+            let keymap = Keymap::new(&config.keys, &config.commands, config.vim)
+                .map_err(|errors| format!("invalid key bindings:\n{}", errors.join("\n")))?;
+            let tags = TagDb::load().map_err(|err| {
+                format!("cannot load tags from {}: {}", TagDb::path().display(), err)
+            })?;
+            let display_profile = config.color.display_profile.clone();
+            match ColorManagement::new(&config.color) {
+                Ok(color) => Ok((config, color, keymap, tags)),
+                Err(err) => Err(format!(
+                    "cannot load display profile {}: {}",
+                    display_profile.unwrap_or_default().display(),
+                    err
+                )),
+            }
+        });
+        let (config, color, keymap, tags) = match startup {
+            Ok(startup) => startup,
+            Err(err_msg) => {
+                let fuu = Self {
+                    current_page: Page::Error(err_msg),
+                    window_geometry: flags.geometry,
+                    ..Default::default()
+                };
+                return (fuu, fonts);
+            }
+        };
+        let mut sources = flags.sources;
+        let session = match (flags.resume, sources.is_empty()) {
+            (true, true) => Session::load_last(),
+            (true, false) => Session::load_for(&sources),
+            (false, _) if config.session.auto_resume => Session::load_for(&sources),
+            (false, _) => None,
+        };
+        if let Some(session) = &session {
+            if sources.is_empty() {
+                sources = session.sources();
+            }
+        }
+        let mut fuu = Self::new(config, color, keymap);
+        fuu.window_geometry = flags.geometry;
+        fuu.tags = tags;
+        fuu.sources = sources.iter().map(ImageSource::key).collect();
+        fuu.pending_session = session;
+        let window_mode = if flags.fullscreen {
+            fuu.set_fullscreen(true)
+        } else {
+            Command::none()
+        };
         (
-            Self::new(),
+            fuu,
             Command::batch([
-                font::load(include_bytes!("../../fonts/icons-subset.ttf").as_slice())
-                    .map(Message::FontLoaded),
-                font::load(include_bytes!("../../fonts/japanese-subset.ttf").as_slice())
-                    .map(Message::FontLoaded),
+                fonts,
+                window_mode,
                 Command::perform(
                     async {
                         create_cache_dir().await.expect("Cannot create cache dir");
-                        read_sources(flags).await
+                        read_sources(sources).await
                     },
                     Message::SourcesLoaded,
                 ),
@@ -61,7 +174,10 @@ impl Application for Fuu {
     }
 
     fn theme(&self) -> Theme {
-        Theme::Dark
+        match self.config.theme {
+            ThemeName::Dark => Theme::Dark,
+            ThemeName::Light => Theme::Light,
+        }
     }
 
     fn update(&mut self, message: Message) -> Command<Message> {
@@ -71,10 +187,17 @@ impl Application for Fuu {
     fn view(&self) -> Element<Message> {
         let content = match &self.current_page {
             Page::Welcome => welcome_page(),
-            Page::Gallery => self.gallery_view(),
-            Page::ShowImage => self.image_preview(),
+            Page::Gallery => column![self.gallery_view(), self.status_view()].into(),
+            Page::ShowImage => column![self.image_preview(), self.status_view()].into(),
+            Page::Compare => column![self.compare_view(), self.status_view()].into(),
             Page::Error(err_msg) => error_view(err_msg),
         };
+        if let Some((position, cursor)) = self.context_menu {
+            return Modal::new(content, self.context_menu_view(position))
+                .at(cursor)
+                .on_blur(Message::HideOverlay)
+                .into();
+        }
         if self.file_drag {
             let overlay = container(text("File Hovered"))
                 .width(self.container_dim.0 as u16 / 2)
@@ -91,20 +214,31 @@ impl Application for Fuu {
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        iced::subscription::events_with(|event, _| match event {
-            Event::Keyboard(keyboard::Event::KeyPressed { key_code, .. }) => {
-                Some(Message::KeyPress(key_code))
-            }
+        let events = iced::subscription::events_with(|event, _| match event {
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key_code,
+                modifiers,
+            }) => Some(Message::KeyPress(key_code, modifiers)),
             Event::Window(window::Event::Resized { width, height }) => {
                 Some(Message::WindowResize { width, height })
             }
+            Event::Window(window::Event::Moved { x, y }) => Some(Message::WindowMoved { x, y }),
             Event::Window(window::Event::FileDropped(file_path)) => {
                 Some(Message::FileDropped(file_path))
             }
             Event::Window(window::Event::FileHovered(_)) => Some(Message::FileHovered),
             Event::Window(window::Event::FilesHoveredLeft) => Some(Message::HideOverlay),
             Event::Window(window::Event::CloseRequested) => Some(Message::CloseRequested),
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                *POINTER.lock().unwrap() = position;
+                None
+            }
             _ => None,
-        })
+        });
+        let dirs = self.watched_dirs();
+        if !self.config.watch || dirs.is_empty() {
+            return events;
+        }
+        Subscription::batch([events, watcher::watch(dirs)])
     }
 }
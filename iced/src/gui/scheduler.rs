@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+
+/// Keeps a fixed number of thumbnail workers busy, picking the most urgent cards first
+#[derive(Debug)]
+pub struct ThumbScheduler {
+    pub workers: usize,
+    running: HashSet<usize>,
+}
+
+impl Default for ThumbScheduler {
+    fn default() -> Self {
+        let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+        Self::new(workers)
+    }
+}
+
+impl ThumbScheduler {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            running: HashSet::new(),
+        }
+    }
+
+    pub fn is_running(&self, index: usize) -> bool {
+        self.running.contains(&index)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.running.is_empty()
+    }
+
+    /// Free the worker of a thumbnail, whether it succeeded or not
+    pub fn finish(&mut self, index: usize) {
+        self.running.remove(&index);
+    }
+
+    /// Start as many `candidates` as there are idle workers,
+    /// `candidates` must be sorted from the most to the least urgent
+    pub fn schedule(&mut self, candidates: impl IntoIterator<Item = usize>) -> Vec<usize> {
+        let idle = self.workers.saturating_sub(self.running.len());
+        let started: Vec<usize> = candidates
+            .into_iter()
+            .filter(|index| !self.running.contains(index))
+            .take(idle)
+            .collect();
+        self.running.extend(&started);
+        started
+    }
+}
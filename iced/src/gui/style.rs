@@ -1,10 +1,13 @@
 use iced::widget::{button, container};
-use iced::Theme;
+use iced::{Color, Theme};
 
 pub const CONTAINER_PADDING: u32 = 10;
 pub const COLUMN_SPACING: u32 = 10;
 pub const ROW_SPACING: u32 = 5;
 pub const DEFAULT_IMG_WIDTH: u32 = 200;
+pub const STATUS_BAR_HEIGHT: u32 = 24;
+pub const CARD_PADDING: u32 = 5;
+pub const BADGE_HEIGHT: u32 = 16;
 
 #[derive(Default)]
 pub enum ImageCard {
@@ -57,3 +60,20 @@ impl container::StyleSheet for ModalStyle {
         }
     }
 }
+
+/// Dark and see-through, for panels drawn over the image
+#[derive(Default)]
+pub struct PanelStyle;
+
+impl container::StyleSheet for PanelStyle {
+    type Style = Theme;
+
+    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            text_color: Some(Color::WHITE),
+            background: Some(Color { a: 0.7, ..Color::BLACK }.into()),
+            border_radius: 6.0.into(),
+            ..Default::default()
+        }
+    }
+}
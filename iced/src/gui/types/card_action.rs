@@ -0,0 +1,9 @@
+/// Entries of the context menu of a gallery card
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardAction {
+    Open,
+    Mark,
+    CopyPath,
+    OpenFolder,
+    ReloadThumbnail,
+}
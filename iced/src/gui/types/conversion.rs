@@ -0,0 +1,65 @@
+use crate::convert::ConvertOptions;
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A batch conversion of marked images, converted a few at a time
+#[derive(Debug)]
+pub struct Conversion {
+    pub options: Arc<ConvertOptions>,
+    queue: VecDeque<usize>,
+    running: usize,
+    converted: usize,
+    pub errors: Vec<String>,
+    total: usize,
+}
+
+impl Conversion {
+    pub fn new(images: Vec<usize>, options: ConvertOptions) -> Self {
+        Self {
+            options: Arc::new(options),
+            total: images.len(),
+            queue: images.into(),
+            running: 0,
+            converted: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Images to start so that `workers` of them are converted at the same time
+    pub fn start(&mut self, workers: usize) -> Vec<usize> {
+        let count = workers.saturating_sub(self.running).min(self.queue.len());
+        self.running += count;
+        self.queue.drain(..count).collect()
+    }
+
+    pub fn finish(&mut self, result: Result<PathBuf, String>) {
+        self.running = self.running.saturating_sub(1);
+        match result {
+            Ok(_) => self.converted += 1,
+            Err(err) => self.errors.push(err),
+        }
+    }
+
+    /// Leave the images that are not started yet
+    pub fn cancel(&mut self) {
+        self.total -= self.queue.len();
+        self.queue.clear();
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.running == 0 && self.queue.is_empty()
+    }
+}
+
+impl fmt::Display for Conversion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let done = self.converted + self.errors.len();
+        write!(f, "converted {} of {}", done, self.total)?;
+        if !self.errors.is_empty() {
+            write!(f, ", {} failed", self.errors.len())?;
+        }
+        Ok(())
+    }
+}
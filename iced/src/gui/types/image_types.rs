@@ -1,8 +1,14 @@
 use bytes::Bytes;
 use crate::gui::style::DEFAULT_IMG_WIDTH;
+use crate::diff::Diff;
+use crate::histogram::Histogram;
 use crate::utils::*;
+use crate::xmp::Metadata;
+use iced::widget::image::Handle;
+use image::{DynamicImage, RgbaImage};
 use std::path::PathBuf;
 use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 #[derive(Default, Clone, Debug)]
 pub enum ThumbState {
@@ -12,14 +18,100 @@ pub enum ThumbState {
     Error,
 }
 
+/// What generating a thumbnail tells about an image
+#[derive(Clone, Copy, Debug)]
+pub struct ThumbInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Dimensions of the image itself
+    pub source_dim: Option<(u32, u32)>,
+    /// Perceptual hash, computed from the thumbnail
+    pub dhash: Option<u64>,
+}
+
 #[derive(Default, Clone, Debug)]
 pub enum ImageState {
     #[default]
     Loading,
-    Loaded(Bytes),
+    Loaded(Preview),
     Error,
 }
 
+/// Decoded RGBA pixels of a preview, ready to be uploaded by the renderer
+#[derive(Clone, Debug)]
+pub struct Preview {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Bytes,
+    pub handle: Handle,
+}
+
+impl Preview {
+    pub fn new(image: DynamicImage) -> Self {
+        let rgba_image = image.into_rgba8();
+        let (width, height) = rgba_image.dimensions();
+        let pixels = Bytes::from(rgba_image.into_raw());
+        Self {
+            width,
+            height,
+            handle: Handle::from_pixels(width, height, pixels.clone()),
+            pixels,
+        }
+    }
+
+    /// Memory held by the decoded pixels in bytes
+    pub fn size(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn to_rgba(&self) -> Option<RgbaImage> {
+        RgbaImage::from_raw(self.width, self.height, self.pixels.to_vec())
+    }
+}
+
+/// Histogram of a preview with its graph ready to be drawn
+#[derive(Clone, Debug)]
+pub struct HistogramPreview {
+    pub histogram: Arc<Histogram>,
+    pub graph: Preview,
+}
+
+impl HistogramPreview {
+    pub fn new(preview: &Preview) -> Self {
+        let histogram = Histogram::new(&preview.pixels);
+        Self {
+            graph: Preview::new(DynamicImage::ImageRgba8(histogram.render())),
+            histogram: Arc::new(histogram),
+        }
+    }
+}
+
+/// A diff between two images with its heatmap ready to be drawn
+#[derive(Clone, Debug)]
+pub struct DiffPreview {
+    pub diff: Arc<Diff>,
+    pub heatmap: Preview,
+}
+
+impl DiffPreview {
+    pub fn new(first: &Preview, second: &Preview) -> Result<Self, String> {
+        let (Some(first), Some(second)) = (first.to_rgba(), second.to_rgba()) else {
+            return Err(String::from("invalid preview"));
+        };
+        let diff = crate::diff::diff(&first, &second)?;
+        Ok(Self {
+            heatmap: Preview::new(DynamicImage::ImageRgba8(diff.heatmap.clone())),
+            diff: Arc::new(diff),
+        })
+    }
+
+    /// The second image over the first one with the given opacity
+    pub fn onion_skin(&self, opacity: f32) -> Preview {
+        let blended = crate::diff::blend(&self.diff.base, &self.diff.overlay, opacity);
+        Preview::new(DynamicImage::ImageRgba8(blended))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum ImageSource {
     Path(PathBuf),
@@ -34,6 +126,28 @@ impl ImageSource {
         }
     }
 
+    /// Absolute path or url identifying the source between runs
+    pub fn key(&self) -> String {
+        match self {
+            Self::Url(url) => url.to_string(),
+            Self::Path(path) => path
+                .canonicalize()
+                .or_else(|_| std::path::absolute(path))
+                .unwrap_or_else(|_| path.clone())
+                .display()
+                .to_string(),
+        }
+    }
+
+    /// Where the thumbnail is cached, files are found by their absolute path
+    /// however they were given
+    pub fn thumbnail(&self) -> PathBuf {
+        match self {
+            Self::Url(url) => thumbnail_path(thumb_path(url.as_str())),
+            Self::Path(_) => thumbnail_path(self.key()),
+        }
+    }
+
     pub fn as_path(&self) -> PathBuf {
         match self {
             Self::Url(url) => thumb_path(url.as_str()),
@@ -56,6 +170,14 @@ pub struct ImageCard {
     pub preview: ImageSource,
     pub thumb_state: ThumbState,
     pub preview_state: ImageState,
+    /// Rating and color label, from the XMP sidecar of local files
+    pub metadata: Metadata,
+    /// Whether the sidecar was read yet, it's done in the background
+    pub metadata_loaded: bool,
+    /// Md5 of the file content, computed once there are tags to look up
+    pub hash: Option<String>,
+    pub source_dim: Option<(u32, u32)>,
+    pub dhash: Option<u64>,
 }
 
 
@@ -66,28 +188,33 @@ impl ImageCard {
         (new_width, new_height)
     }
 
-    fn from_path(image_path: PathBuf) -> Self {
-        let thumb_path = thumb_path(&image_path);
+    pub fn new(image_source: ImageSource) -> Self {
         Self {
-            preview: ImageSource::Path(image_path),
-            thumb: thumb_path,
+            thumb: image_source.thumbnail(),
+            preview: image_source,
             ..Default::default()
         }
     }
 
-    fn from_url(url: url::Url) -> Self {
-        let thumb_path = thumb_path(thumb_path(url.as_str()));
-        Self {
-            preview: ImageSource::Url(url),
-            thumb: thumb_path,
-            ..Default::default()
-        }
+    /// Delete the cached thumbnail so it's generated again
+    pub fn remove_thumb(&mut self) {
+        let _ = std::fs::remove_file(&self.thumb);
+        let _ = std::fs::remove_file(self.dhash_path());
+        self.thumb_state = ThumbState::Loading;
+        self.dhash = None;
     }
 
-    pub fn new(image_source: ImageSource) -> Self {
-        match image_source {
-            ImageSource::Path(path) => Self::from_path(path),
-            ImageSource::Url(url) => Self::from_url(url),
+    /// Where the perceptual hash is cached, next to the thumbnail
+    pub fn dhash_path(&self) -> PathBuf {
+        self.thumb.with_extension("dhash")
+    }
+
+    /// Set the width of the thumbnail to generate
+    pub fn with_width(self, width: u32) -> Self {
+        Self {
+            width,
+            height: width,
+            ..self
         }
     }
 }
@@ -101,6 +228,11 @@ impl Default for ImageCard {
             preview: Default::default(),
             thumb_state: Default::default(),
             preview_state: Default::default(),
+            metadata: Default::default(),
+            metadata_loaded: false,
+            hash: None,
+            source_dim: None,
+            dhash: None,
         }
     }
 }
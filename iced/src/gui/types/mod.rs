@@ -1,5 +1,11 @@
+mod card_action;
+mod conversion;
 mod image_types;
 mod page;
+mod prompt;
 
+pub use card_action::*;
+pub use conversion::*;
 pub use image_types::*;
 pub use page::*;
+pub use prompt::*;
@@ -4,5 +4,28 @@ pub enum Page {
     Welcome,
     Gallery,
     ShowImage,
+    /// Marked images side by side
+    Compare,
     Error(String),
 }
+
+/// How the compare page shows the marked images
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompareMode {
+    #[default]
+    SideBySide,
+    /// Per pixel difference of the first two images
+    Heatmap,
+    /// The second image blended over the first one
+    OnionSkin,
+}
+
+impl CompareMode {
+    pub fn next(self) -> Self {
+        match self {
+            CompareMode::SideBySide => CompareMode::Heatmap,
+            CompareMode::Heatmap => CompareMode::OnionSkin,
+            CompareMode::OnionSkin => CompareMode::SideBySide,
+        }
+    }
+}
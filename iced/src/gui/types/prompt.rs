@@ -0,0 +1,30 @@
+/// What the text typed in the status bar is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    /// Tags to add to the selected image, or remove with a leading `-`
+    Tags,
+    /// Tag query the gallery is filtered with
+    Filter,
+    /// Options of the conversion of the marked images
+    Convert,
+}
+
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub value: String,
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind, value: String) -> Self {
+        Self { kind, value }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self.kind {
+            PromptKind::Tags => "tags:",
+            PromptKind::Filter => "filter:",
+            PromptKind::Convert => "convert:",
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::gui::keymap::Action;
+use iced::keyboard::{KeyCode, Modifiers};
+use std::fmt;
+
+/// Where a complete vim command moves the selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    /// Repeat a navigation action
+    Repeat(Action, usize),
+    /// Go to a position of the view, `None` being the last one
+    Jump(Option<usize>),
+    /// Move by half a screen, forward when true
+    HalfPage(bool, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VimKey {
+    /// The key was consumed, more keys are expected
+    Pending,
+    Motion(Motion),
+    /// Not a vim key, it goes through the keymap
+    Unhandled,
+}
+
+/// Keys typed so far of an incomplete command like `20G` or `gg`
+#[derive(Debug, Clone, Default)]
+pub struct PendingKeys {
+    count: Option<usize>,
+    g: bool,
+}
+
+impl PendingKeys {
+    pub fn is_empty(&self) -> bool {
+        self.count.is_none() && !self.g
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn take_count(&mut self) -> Option<usize> {
+        let count = self.count;
+        self.clear();
+        count
+    }
+
+    pub fn feed(&mut self, key: KeyCode, modifiers: Modifiers) -> VimKey {
+        let shift = modifiers.shift();
+        if modifiers.control() || modifiers.alt() || modifiers.logo() {
+            let forward = match key {
+                KeyCode::D if modifiers.control() => true,
+                KeyCode::U if modifiers.control() => false,
+                _ => {
+                    self.clear();
+                    return VimKey::Unhandled;
+                }
+            };
+            let count = self.take_count().unwrap_or(1);
+            return VimKey::Motion(Motion::HalfPage(forward, count));
+        }
+        if let Some(digit) = digit(key) {
+            // a leading zero is not a count
+            if digit != 0 || self.count.is_some() {
+                let count = self.count.unwrap_or(0);
+                self.count = Some(count.saturating_mul(10).saturating_add(digit));
+                self.g = false;
+                return VimKey::Pending;
+            }
+        }
+        let motion = match (key, shift) {
+            (KeyCode::H, false) => Motion::Repeat(Action::Previous, 1),
+            (KeyCode::L, false) => Motion::Repeat(Action::Next, 1),
+            (KeyCode::K, false) => Motion::Repeat(Action::Up, 1),
+            (KeyCode::J, false) => Motion::Repeat(Action::Down, 1),
+            (KeyCode::G, true) => {
+                let count = self.take_count();
+                return VimKey::Motion(Motion::Jump(count.map(|count| count.max(1) - 1)));
+            }
+            (KeyCode::G, false) if self.g => {
+                let count = self.take_count();
+                return VimKey::Motion(Motion::Jump(Some(count.unwrap_or(1).max(1) - 1)));
+            }
+            (KeyCode::G, false) => {
+                self.g = true;
+                return VimKey::Pending;
+            }
+            (KeyCode::Escape, _) if !self.is_empty() => {
+                self.clear();
+                return VimKey::Pending;
+            }
+            _ => {
+                self.clear();
+                return VimKey::Unhandled;
+            }
+        };
+        let count = self.take_count().unwrap_or(1);
+        match motion {
+            Motion::Repeat(action, _) => VimKey::Motion(Motion::Repeat(action, count)),
+            motion => VimKey::Motion(motion),
+        }
+    }
+}
+
+impl fmt::Display for PendingKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(count) = self.count {
+            write!(f, "{count}")?;
+        }
+        if self.g {
+            f.write_str("g")?;
+        }
+        Ok(())
+    }
+}
+
+fn digit(key: KeyCode) -> Option<usize> {
+    let digit = match key {
+        KeyCode::Key0 | KeyCode::Numpad0 => 0,
+        KeyCode::Key1 | KeyCode::Numpad1 => 1,
+        KeyCode::Key2 | KeyCode::Numpad2 => 2,
+        KeyCode::Key3 | KeyCode::Numpad3 => 3,
+        KeyCode::Key4 | KeyCode::Numpad4 => 4,
+        KeyCode::Key5 | KeyCode::Numpad5 => 5,
+        KeyCode::Key6 | KeyCode::Numpad6 => 6,
+        KeyCode::Key7 | KeyCode::Numpad7 => 7,
+        KeyCode::Key8 | KeyCode::Numpad8 => 8,
+        KeyCode::Key9 | KeyCode::Numpad9 => 9,
+        _ => return None,
+    };
+    Some(digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(keys: &[(KeyCode, Modifiers)]) -> (PendingKeys, Vec<VimKey>) {
+        let mut pending = PendingKeys::default();
+        let results = keys
+            .iter()
+            .map(|&(key, modifiers)| pending.feed(key, modifiers))
+            .collect();
+        (pending, results)
+    }
+
+    fn plain(keys: &[KeyCode]) -> Vec<(KeyCode, Modifiers)> {
+        keys.iter().map(|&key| (key, Modifiers::empty())).collect()
+    }
+
+    #[test]
+    fn motion_without_count_moves_once() {
+        let (pending, results) = feed(&plain(&[KeyCode::J]));
+        assert_eq!(results, [VimKey::Motion(Motion::Repeat(Action::Down, 1))]);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn count_repeats_the_motion() {
+        let (pending, results) = feed(&plain(&[KeyCode::Key1, KeyCode::Numpad2, KeyCode::L]));
+        assert_eq!(
+            results,
+            [
+                VimKey::Pending,
+                VimKey::Pending,
+                VimKey::Motion(Motion::Repeat(Action::Next, 12)),
+            ]
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn count_is_shown_while_pending() {
+        let (pending, _) = feed(&plain(&[KeyCode::Key2, KeyCode::Key0, KeyCode::G]));
+        assert_eq!(pending.to_string(), "20g");
+    }
+
+    #[test]
+    fn huge_count_saturates() {
+        let mut keys = plain(&[KeyCode::Key9; 30]);
+        keys.push((KeyCode::K, Modifiers::empty()));
+        let (_, results) = feed(&keys);
+        assert_eq!(
+            results.last(),
+            Some(&VimKey::Motion(Motion::Repeat(Action::Up, usize::MAX)))
+        );
+    }
+
+    #[test]
+    fn leading_zero_is_not_a_count() {
+        let (pending, results) = feed(&plain(&[KeyCode::Key0]));
+        assert_eq!(results, [VimKey::Unhandled]);
+        assert!(pending.is_empty());
+
+        let (_, results) = feed(&plain(&[KeyCode::Key1, KeyCode::Key0, KeyCode::H]));
+        assert_eq!(results[2], VimKey::Motion(Motion::Repeat(Action::Previous, 10)));
+    }
+
+    #[test]
+    fn gg_jumps_to_the_first_or_counted_position() {
+        let (pending, results) = feed(&plain(&[KeyCode::G, KeyCode::G]));
+        assert_eq!(results, [VimKey::Pending, VimKey::Motion(Motion::Jump(Some(0)))]);
+        assert!(pending.is_empty());
+
+        let (_, results) = feed(&plain(&[KeyCode::Key5, KeyCode::G, KeyCode::G]));
+        assert_eq!(results[2], VimKey::Motion(Motion::Jump(Some(4))));
+    }
+
+    #[test]
+    fn shift_g_jumps_to_the_last_or_counted_position() {
+        let (_, results) = feed(&[(KeyCode::G, Modifiers::SHIFT)]);
+        assert_eq!(results, [VimKey::Motion(Motion::Jump(None))]);
+
+        let (_, results) = feed(&[
+            (KeyCode::Key3, Modifiers::empty()),
+            (KeyCode::G, Modifiers::SHIFT),
+        ]);
+        assert_eq!(results[1], VimKey::Motion(Motion::Jump(Some(2))));
+    }
+
+    #[test]
+    fn half_page_takes_the_count() {
+        let (_, results) = feed(&[
+            (KeyCode::Key2, Modifiers::empty()),
+            (KeyCode::D, Modifiers::CTRL),
+        ]);
+        assert_eq!(results[1], VimKey::Motion(Motion::HalfPage(true, 2)));
+
+        let (_, results) = feed(&[(KeyCode::U, Modifiers::CTRL)]);
+        assert_eq!(results, [VimKey::Motion(Motion::HalfPage(false, 1))]);
+    }
+
+    #[test]
+    fn unhandled_key_resets_the_pending_keys() {
+        let (pending, results) = feed(&plain(&[KeyCode::Key4, KeyCode::G, KeyCode::Space]));
+        assert_eq!(results[2], VimKey::Unhandled);
+        assert!(pending.is_empty());
+
+        let (pending, results) = feed(&[
+            (KeyCode::Key4, Modifiers::empty()),
+            (KeyCode::S, Modifiers::CTRL),
+        ]);
+        assert_eq!(results[1], VimKey::Unhandled);
+        assert!(pending.is_empty());
+
+        // the count is gone, so the next motion moves once
+        let mut pending = PendingKeys::default();
+        pending.feed(KeyCode::Key7, Modifiers::empty());
+        pending.feed(KeyCode::X, Modifiers::empty());
+        assert_eq!(
+            pending.feed(KeyCode::J, Modifiers::empty()),
+            VimKey::Motion(Motion::Repeat(Action::Down, 1))
+        );
+    }
+
+    #[test]
+    fn escape_clears_the_pending_keys() {
+        let (pending, results) = feed(&plain(&[KeyCode::Key3, KeyCode::Escape]));
+        assert_eq!(results[1], VimKey::Pending);
+        assert!(pending.is_empty());
+
+        let (_, results) = feed(&plain(&[KeyCode::Escape]));
+        assert_eq!(results, [VimKey::Unhandled]);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::gui::Message;
+use crate::utils::TEMPORARY_EXTENSION;
+use iced::futures::channel::mpsc;
+use iced::futures::{SinkExt, StreamExt};
+use iced::{subscription, Subscription};
+use indexmap::IndexMap;
+use notify::event::{AccessKind, AccessMode, EventKind, ModifyKind, RenameMode};
+use notify::{RecursiveMode, Watcher};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// How long a file must be left alone before its changes are reported,
+/// so a file is reloaded once its writer is done with it
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// Something that happened to a file of a watched directory
+#[derive(Debug, Clone)]
+pub enum FileChange {
+    Created(PathBuf),
+    Removed(PathBuf),
+    Modified(PathBuf),
+}
+
+impl FileChange {
+    fn path(&self) -> &PathBuf {
+        match self {
+            Self::Created(path) | Self::Removed(path) | Self::Modified(path) => path,
+        }
+    }
+
+    /// The change that sums up this one followed by `newer`
+    fn then(self, newer: FileChange) -> FileChange {
+        match (self, newer) {
+            // a new file is loaded whole anyway
+            (Self::Created(path), Self::Modified(_)) => Self::Created(path),
+            (_, newer) => newer,
+        }
+    }
+}
+
+fn file_changes(event: notify::Event) -> Vec<FileChange> {
+    // files being rotated are written next to them first
+    let paths = event
+        .paths
+        .into_iter()
+        .filter(|path| path.extension().is_none_or(|extension| extension != TEMPORARY_EXTENSION));
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            paths.map(FileChange::Created).collect()
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            paths.map(FileChange::Removed).collect()
+        }
+        // inotify also reports both sides of a rename separately
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => Vec::new(),
+        EventKind::Modify(ModifyKind::Name(_)) => paths
+            .map(|path| {
+                if path.exists() {
+                    FileChange::Created(path)
+                } else {
+                    FileChange::Removed(path)
+                }
+            })
+            .collect(),
+        EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any)
+        | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+            paths.map(FileChange::Modified).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Report the changes made to the files of `dirs`, not their subdirectories
+pub fn watch(dirs: Vec<PathBuf>) -> Subscription<Message> {
+    subscription::channel(dirs.clone(), 100, |mut output| async move {
+        let (sender, mut receiver) = mpsc::unbounded();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.unbounded_send(event);
+        });
+        // dropping the watcher would stop it
+        let _watcher = match watcher {
+            Ok(mut watcher) => {
+                for dir in &dirs {
+                    if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                        eprintln!("cannot watch {}: {}", dir.display(), err);
+                    }
+                }
+                Some(watcher)
+            }
+            Err(err) => {
+                eprintln!("cannot watch directories: {err}");
+                None
+            }
+        };
+        // changes waiting for their file to be quiet, with the time of the last one
+        let mut pending: IndexMap<PathBuf, (FileChange, Instant)> = IndexMap::new();
+        loop {
+            // the oldest change is the first one due
+            let deadline = pending.first().map(|(_, (_, at))| *at + QUIET_PERIOD);
+            let event = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline.into(), receiver.next())
+                    .await
+                    .ok(),
+                None => Some(receiver.next().await),
+            };
+            match event {
+                Some(Some(Ok(event))) => {
+                    let now = Instant::now();
+                    for change in file_changes(event) {
+                        let change = match pending.shift_remove(change.path()) {
+                            Some((older, _)) => older.then(change),
+                            None => change,
+                        };
+                        pending.insert(change.path().clone(), (change, now));
+                    }
+                }
+                Some(Some(Err(err))) => eprintln!("directory watcher error: {err}"),
+                // the watcher is gone, nothing more will happen
+                Some(None) => {
+                    for (_, (change, _)) in pending.drain(..) {
+                        let _ = output.send(Message::FileChanged(change)).await;
+                    }
+                    std::future::pending::<()>().await;
+                }
+                // the oldest change is due
+                None => (),
+            }
+            let now = Instant::now();
+            while let Some((_, (_, at))) = pending.first() {
+                if now < *at + QUIET_PERIOD {
+                    break;
+                }
+                if let Some((_, (change, _))) = pending.shift_remove_index(0) {
+                    let _ = output.send(Message::FileChanged(change)).await;
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,251 @@
+//! Draws a panel over the top right corner of its content, without taking any room from it
+
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::overlay;
+use iced::advanced::renderer;
+use iced::advanced::widget::{Operation, Tree, Widget};
+use iced::advanced::{self, Clipboard, Shell};
+use iced::event;
+use iced::mouse;
+use iced::{Element, Event, Length, Point, Rectangle, Size, Vector};
+
+/// Room left between the panel and the edges of the content
+const MARGIN: f32 = 8.0;
+
+pub struct CornerOverlay<'a, Message, Renderer> {
+    content: Element<'a, Message, Renderer>,
+    panel: Element<'a, Message, Renderer>,
+}
+
+impl<'a, Message, Renderer> CornerOverlay<'a, Message, Renderer> {
+    pub fn new(
+        content: impl Into<Element<'a, Message, Renderer>>,
+        panel: impl Into<Element<'a, Message, Renderer>>,
+    ) -> Self {
+        Self {
+            content: content.into(),
+            panel: panel.into(),
+        }
+    }
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer> for CornerOverlay<'a, Message, Renderer>
+where
+    Renderer: advanced::Renderer,
+{
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.content), Tree::new(&self.panel)]
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(&[&self.content, &self.panel]);
+    }
+
+    fn width(&self) -> Length {
+        self.content.as_widget().width()
+    }
+
+    fn height(&self) -> Length {
+        self.content.as_widget().height()
+    }
+
+    fn layout(&self, renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        self.content.as_widget().layout(renderer, limits)
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        viewport: &Rectangle,
+    ) -> event::Status {
+        self.content.as_widget_mut().on_event(
+            &mut tree.children[0],
+            event,
+            layout,
+            cursor,
+            renderer,
+            clipboard,
+            shell,
+            viewport,
+        )
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Renderer::Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        self.content.as_widget().draw(
+            &tree.children[0],
+            renderer,
+            theme,
+            style,
+            layout,
+            cursor,
+            viewport,
+        );
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.content.as_widget().mouse_interaction(
+            &tree.children[0],
+            layout,
+            cursor,
+            viewport,
+            renderer,
+        )
+    }
+
+    fn operate(
+        &self,
+        tree: &mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+        operation: &mut dyn Operation<Message>,
+    ) {
+        self.content
+            .as_widget()
+            .operate(&mut tree.children[0], layout, renderer, operation);
+    }
+
+    fn overlay<'b>(
+        &'b mut self,
+        tree: &'b mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+    ) -> Option<overlay::Element<'b, Message, Renderer>> {
+        let (content_tree, panel_tree) = tree.children.split_at_mut(1);
+        let bounds = layout.bounds();
+        let panel = overlay::Element::new(
+            bounds.position(),
+            Box::new(Panel {
+                content: &mut self.panel,
+                tree: &mut panel_tree[0],
+                size: bounds.size(),
+            }),
+        );
+        let mut children = vec![panel];
+        // the overlays of the content, if any, stay above the panel
+        if let Some(overlay) =
+            self.content
+                .as_widget_mut()
+                .overlay(&mut content_tree[0], layout, renderer)
+        {
+            children.push(overlay);
+        }
+        Some(overlay::Group::with_children(children).overlay())
+    }
+}
+
+struct Panel<'a, 'b, Message, Renderer> {
+    content: &'b mut Element<'a, Message, Renderer>,
+    tree: &'b mut Tree,
+    /// Size of the content the panel is drawn over
+    size: Size,
+}
+
+impl<'a, 'b, Message, Renderer> overlay::Overlay<Message, Renderer>
+    for Panel<'a, 'b, Message, Renderer>
+where
+    Renderer: advanced::Renderer,
+{
+    fn layout(&self, renderer: &Renderer, _bounds: Size, position: Point) -> layout::Node {
+        let room = Size::new(
+            (self.size.width - 2.0 * MARGIN).max(0.0),
+            (self.size.height - 2.0 * MARGIN).max(0.0),
+        );
+        let mut node = self
+            .content
+            .as_widget()
+            .layout(renderer, &layout::Limits::new(Size::ZERO, room));
+        // only the panel is laid out so the content keeps the cursor around it
+        let x = (self.size.width - node.size().width - MARGIN).max(0.0);
+        node.move_to(position + Vector::new(x, MARGIN));
+        node
+    }
+
+    fn on_event(
+        &mut self,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) -> event::Status {
+        self.content.as_widget_mut().on_event(
+            self.tree,
+            event,
+            layout,
+            cursor,
+            renderer,
+            clipboard,
+            shell,
+            &layout.bounds(),
+        )
+    }
+
+    fn draw(
+        &self,
+        renderer: &mut Renderer,
+        theme: &Renderer::Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+    ) {
+        self.content.as_widget().draw(
+            self.tree,
+            renderer,
+            theme,
+            style,
+            layout,
+            cursor,
+            &layout.bounds(),
+        );
+    }
+
+    fn mouse_interaction(
+        &self,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.content.as_widget().mouse_interaction(
+            self.tree,
+            layout,
+            cursor,
+            viewport,
+            renderer,
+        )
+    }
+}
+
+impl<'a, Message, Renderer> From<CornerOverlay<'a, Message, Renderer>>
+    for Element<'a, Message, Renderer>
+where
+    Renderer: 'a + advanced::Renderer,
+    Message: 'a,
+{
+    fn from(corner_overlay: CornerOverlay<'a, Message, Renderer>) -> Self {
+        Element::new(corner_overlay)
+    }
+}
@@ -0,0 +1,220 @@
+//! The whole image with a crop rectangle that is drawn by dragging the mouse
+
+use crate::crop::{AspectRatio, CropRect};
+use iced::advanced::image;
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::renderer;
+use iced::advanced::widget::{tree, Tree, Widget};
+use iced::advanced::{Clipboard, Shell};
+use iced::event;
+use iced::mouse;
+use iced::{Color, Element, Event, Length, Point, Rectangle, Size};
+
+/// Darkens the part of the image that is cut off
+const SHADE: Color = Color::from_rgba(0.0, 0.0, 0.0, 0.6);
+
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    /// Corner where the mouse was pressed, in image pixels
+    dragged_from: Option<(f32, f32)>,
+}
+
+pub struct CropArea<'a, Message> {
+    handle: image::Handle,
+    image_size: (u32, u32),
+    rect: CropRect,
+    aspect: AspectRatio,
+    on_change: Box<dyn Fn(CropRect) -> Message + 'a>,
+}
+
+impl<'a, Message> CropArea<'a, Message> {
+    pub fn new(
+        handle: image::Handle,
+        image_size: (u32, u32),
+        rect: CropRect,
+        aspect: AspectRatio,
+        on_change: impl Fn(CropRect) -> Message + 'a,
+    ) -> Self {
+        Self {
+            handle,
+            image_size,
+            rect,
+            aspect,
+            on_change: Box::new(on_change),
+        }
+    }
+
+    /// Where the image is drawn, fitting in `bounds`
+    fn image_bounds(&self, bounds: Rectangle) -> Rectangle {
+        let (width, height) = (self.image_size.0.max(1) as f32, self.image_size.1.max(1) as f32);
+        let scale = (bounds.width / width).min(bounds.height / height);
+        let size = Size::new(width * scale, height * scale);
+        let center = bounds.center();
+        Rectangle::new(
+            Point::new(center.x - size.width / 2.0, center.y - size.height / 2.0),
+            size,
+        )
+    }
+
+    fn to_image(&self, image_bounds: Rectangle, position: Point) -> (f32, f32) {
+        let scale = self.image_size.0.max(1) as f32 / image_bounds.width;
+        (
+            (position.x - image_bounds.x) * scale,
+            (position.y - image_bounds.y) * scale,
+        )
+    }
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer> for CropArea<'a, Message>
+where
+    Renderer: image::Renderer<Handle = image::Handle>,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn width(&self) -> Length {
+        Length::Fill
+    }
+
+    fn height(&self) -> Length {
+        Length::Fill
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        layout::Node::new(limits.width(Length::Fill).height(Length::Fill).max())
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) -> event::Status {
+        let image_bounds = self.image_bounds(layout.bounds());
+        let state = tree.state.downcast_mut::<State>();
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let Some(position) = cursor.position_over(image_bounds) else {
+                    return event::Status::Ignored;
+                };
+                state.dragged_from = Some(self.to_image(image_bounds, position));
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                match state.dragged_from.take() {
+                    Some(_) => event::Status::Captured,
+                    None => event::Status::Ignored,
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                let Some(start) = state.dragged_from else {
+                    return event::Status::Ignored;
+                };
+                let end = self.to_image(image_bounds, position);
+                let rect = CropRect::from_corners(start, end, self.aspect, self.image_size);
+                shell.publish((self.on_change)(rect));
+                event::Status::Captured
+            }
+            _ => event::Status::Ignored,
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        _tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        if cursor.is_over(self.image_bounds(layout.bounds())) {
+            mouse::Interaction::Crosshair
+        } else {
+            mouse::Interaction::Idle
+        }
+    }
+
+    fn draw(
+        &self,
+        _tree: &Tree,
+        renderer: &mut Renderer,
+        _theme: &Renderer::Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+    ) {
+        let image_bounds = self.image_bounds(layout.bounds());
+        let scale = image_bounds.width / self.image_size.0.max(1) as f32;
+        let rect = Rectangle::new(
+            Point::new(
+                image_bounds.x + self.rect.x as f32 * scale,
+                image_bounds.y + self.rect.y as f32 * scale,
+            ),
+            Size::new(self.rect.width as f32 * scale, self.rect.height as f32 * scale),
+        );
+        let quad = |bounds: Rectangle| renderer::Quad {
+            bounds,
+            border_radius: 0.0.into(),
+            border_width: 0.0,
+            border_color: Color::TRANSPARENT,
+        };
+        image::Renderer::draw(renderer, self.handle.clone(), image_bounds);
+        renderer.with_layer(image_bounds, |renderer| {
+            let right = rect.x + rect.width;
+            let bottom = rect.y + rect.height;
+            let image_right = image_bounds.x + image_bounds.width;
+            let image_bottom = image_bounds.y + image_bounds.height;
+            let shades = [
+                // above and below the rectangle over the whole width
+                Rectangle::new(
+                    image_bounds.position(),
+                    Size::new(image_bounds.width, rect.y - image_bounds.y),
+                ),
+                Rectangle::new(
+                    Point::new(image_bounds.x, bottom),
+                    Size::new(image_bounds.width, image_bottom - bottom),
+                ),
+                // on its sides
+                Rectangle::new(
+                    Point::new(image_bounds.x, rect.y),
+                    Size::new(rect.x - image_bounds.x, rect.height),
+                ),
+                Rectangle::new(Point::new(right, rect.y), Size::new(image_right - right, rect.height)),
+            ];
+            for shade in shades {
+                if shade.width > 0.0 && shade.height > 0.0 {
+                    renderer.fill_quad(quad(shade), SHADE);
+                }
+            }
+            renderer.fill_quad(
+                renderer::Quad {
+                    border_width: 1.0,
+                    border_color: Color::WHITE,
+                    ..quad(rect)
+                },
+                Color::TRANSPARENT,
+            );
+        });
+    }
+}
+
+impl<'a, Message, Renderer> From<CropArea<'a, Message>> for Element<'a, Message, Renderer>
+where
+    Renderer: image::Renderer<Handle = image::Handle> + 'a,
+    Message: 'a,
+{
+    fn from(area: CropArea<'a, Message>) -> Self {
+        Element::new(area)
+    }
+}
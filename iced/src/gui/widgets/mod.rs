@@ -1 +1,5 @@
+pub mod corner_overlay;
+pub mod crop_area;
 pub mod modal;
+pub mod synced_viewer;
+pub mod wheel_zoom;
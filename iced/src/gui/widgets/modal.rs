@@ -15,6 +15,7 @@ pub struct Modal<'a, Message, Renderer> {
     base: Element<'a, Message, Renderer>,
     modal: Element<'a, Message, Renderer>,
     on_blur: Option<Message>,
+    position: Option<Point>,
 }
 
 impl<'a, Message, Renderer> Modal<'a, Message, Renderer> {
@@ -27,6 +28,16 @@ impl<'a, Message, Renderer> Modal<'a, Message, Renderer> {
             base: base.into(),
             modal: modal.into(),
             on_blur: None,
+            position: None,
+        }
+    }
+
+    /// Places the top left corner of the modal element at `position`
+    /// instead of centering it, keeping it inside the base element
+    pub fn at(self, position: Point) -> Self {
+        Self {
+            position: Some(position),
+            ..self
         }
     }
 
@@ -130,6 +141,7 @@ where
                 tree: &mut state.children[1],
                 size: layout.bounds().size(),
                 on_blur: self.on_blur.clone(),
+                position: self.position,
             }),
         ))
     }
@@ -172,6 +184,7 @@ struct Overlay<'a, 'b, Message, Renderer> {
     tree: &'b mut widget::Tree,
     size: Size,
     on_blur: Option<Message>,
+    position: Option<Point>,
 }
 
 impl<'a, 'b, Message, Renderer> overlay::Overlay<Message, Renderer>
@@ -191,7 +204,16 @@ where
             .height(Length::Fill);
 
         let mut child = self.content.as_widget().layout(renderer, &limits);
-        child.align(Alignment::Center, Alignment::Center, limits.max());
+        match self.position {
+            Some(at) => {
+                let free = limits.max() - child.size();
+                child.move_to(Point::new(
+                    (at.x - position.x).clamp(0.0, free.width.max(0.0)),
+                    (at.y - position.y).clamp(0.0, free.height.max(0.0)),
+                ));
+            }
+            None => child.align(Alignment::Center, Alignment::Center, limits.max()),
+        }
 
         let mut node = layout::Node::with_children(self.size, vec![child]);
         node.move_to(position);
@@ -0,0 +1,234 @@
+//! An image viewer whose zoom and pan live in the application, so several
+//! viewers can show the same part of different images
+
+use iced::advanced::image;
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::renderer;
+use iced::advanced::widget::{tree, Tree, Widget};
+use iced::advanced::{Clipboard, Shell};
+use iced::event;
+use iced::mouse;
+use iced::{Element, Event, Length, Point, Rectangle, Size, Vector};
+
+const MAX_SCALE: f32 = 32.0;
+
+/// Zoom and pan shared by the viewers, independent of the image size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewTransform {
+    /// Zoom relative to the image fitting its viewer
+    pub scale: f32,
+    /// Point of the image shown at the center of the viewer, from 0 to 1
+    pub center: Vector,
+}
+
+impl Default for ViewTransform {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            center: Vector::new(0.5, 0.5),
+        }
+    }
+}
+
+impl ViewTransform {
+    pub fn zoom(self, factor: f32) -> Self {
+        Self {
+            scale: self.scale * factor,
+            ..self
+        }
+        .clamped()
+    }
+
+    fn clamped(self) -> Self {
+        let scale = self.scale.clamp(1.0, MAX_SCALE);
+        if scale <= 1.0 {
+            return Self::default();
+        }
+        Self {
+            scale,
+            center: Vector::new(self.center.x.clamp(0.0, 1.0), self.center.y.clamp(0.0, 1.0)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    grabbed_at: Option<Point>,
+    starting_center: Vector,
+}
+
+pub struct SyncedViewer<'a, Message> {
+    handle: image::Handle,
+    image_size: Size,
+    transform: ViewTransform,
+    on_change: Box<dyn Fn(ViewTransform) -> Message + 'a>,
+}
+
+impl<'a, Message> SyncedViewer<'a, Message> {
+    pub fn new(
+        handle: image::Handle,
+        (width, height): (u32, u32),
+        transform: ViewTransform,
+        on_change: impl Fn(ViewTransform) -> Message + 'a,
+    ) -> Self {
+        Self {
+            handle,
+            image_size: Size::new(width.max(1) as f32, height.max(1) as f32),
+            transform,
+            on_change: Box::new(on_change),
+        }
+    }
+
+    /// Size of the image drawn in `bounds`
+    fn displayed_size(&self, bounds: Rectangle) -> Size {
+        let fit = (bounds.width / self.image_size.width).min(bounds.height / self.image_size.height);
+        let scale = fit * self.transform.scale;
+        Size::new(self.image_size.width * scale, self.image_size.height * scale)
+    }
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer> for SyncedViewer<'a, Message>
+where
+    Renderer: image::Renderer<Handle = image::Handle>,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn width(&self) -> Length {
+        Length::Fill
+    }
+
+    fn height(&self) -> Length {
+        Length::Fill
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        layout::Node::new(limits.width(Length::Fill).height(Length::Fill).max())
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) -> event::Status {
+        let bounds = layout.bounds();
+        let size = self.displayed_size(bounds);
+        let state = tree.state.downcast_mut::<State>();
+        match event {
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                let Some(position) = cursor.position_over(bounds) else {
+                    return event::Status::Ignored;
+                };
+                let y = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } | mouse::ScrollDelta::Pixels { y, .. } => y,
+                };
+                if y == 0.0 {
+                    return event::Status::Ignored;
+                }
+                let factor = if y > 0.0 { 1.25 } else { 0.8 };
+                let mut transform = self.transform.zoom(factor);
+                // keep the point under the cursor in place
+                let from_center = position - bounds.center();
+                let point = self.transform.center
+                    + Vector::new(from_center.x / size.width, from_center.y / size.height);
+                let ratio = self.transform.scale / transform.scale;
+                transform.center = point
+                    - Vector::new(
+                        from_center.x / size.width * ratio,
+                        from_center.y / size.height * ratio,
+                    );
+                shell.publish((self.on_change)(transform.clamped()));
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let Some(position) = cursor.position_over(bounds) else {
+                    return event::Status::Ignored;
+                };
+                state.grabbed_at = Some(position);
+                state.starting_center = self.transform.center;
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                match state.grabbed_at.take() {
+                    Some(_) => event::Status::Captured,
+                    None => event::Status::Ignored,
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                let Some(origin) = state.grabbed_at else {
+                    return event::Status::Ignored;
+                };
+                let delta = position - origin;
+                let transform = ViewTransform {
+                    center: state.starting_center
+                        - Vector::new(delta.x / size.width, delta.y / size.height),
+                    ..self.transform
+                };
+                shell.publish((self.on_change)(transform.clamped()));
+                event::Status::Captured
+            }
+            _ => event::Status::Ignored,
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        let state = tree.state.downcast_ref::<State>();
+        if state.grabbed_at.is_some() {
+            mouse::Interaction::Grabbing
+        } else if cursor.is_over(layout.bounds()) {
+            mouse::Interaction::Grab
+        } else {
+            mouse::Interaction::Idle
+        }
+    }
+
+    fn draw(
+        &self,
+        _tree: &Tree,
+        renderer: &mut Renderer,
+        _theme: &Renderer::Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        let size = self.displayed_size(bounds);
+        let center = bounds.center();
+        let top_left = Point::new(
+            center.x - self.transform.center.x * size.width,
+            center.y - self.transform.center.y * size.height,
+        );
+        renderer.with_layer(bounds, |renderer| {
+            image::Renderer::draw(renderer, self.handle.clone(), Rectangle::new(top_left, size));
+        });
+    }
+}
+
+impl<'a, Message, Renderer> From<SyncedViewer<'a, Message>> for Element<'a, Message, Renderer>
+where
+    Renderer: image::Renderer<Handle = image::Handle> + 'a,
+    Message: 'a,
+{
+    fn from(viewer: SyncedViewer<'a, Message>) -> Self {
+        Element::new(viewer)
+    }
+}
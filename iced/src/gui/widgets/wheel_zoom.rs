@@ -0,0 +1,180 @@
+//! Turns ctrl + mouse wheel over its content into zoom messages, before the content scrolls
+
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::overlay;
+use iced::advanced::renderer;
+use iced::advanced::widget::{tree, Operation, Tree, Widget};
+use iced::advanced::{self, Clipboard, Shell};
+use iced::event;
+use iced::keyboard;
+use iced::mouse;
+use iced::{Element, Event, Length, Point, Rectangle};
+
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    modifiers: keyboard::Modifiers,
+}
+
+pub struct WheelZoom<'a, Message, Renderer> {
+    content: Element<'a, Message, Renderer>,
+    /// Zooming in when true, at the cursor position
+    on_zoom: Box<dyn Fn(bool, Point) -> Message + 'a>,
+}
+
+impl<'a, Message, Renderer> WheelZoom<'a, Message, Renderer> {
+    pub fn new(
+        content: impl Into<Element<'a, Message, Renderer>>,
+        on_zoom: impl Fn(bool, Point) -> Message + 'a,
+    ) -> Self {
+        Self {
+            content: content.into(),
+            on_zoom: Box::new(on_zoom),
+        }
+    }
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer> for WheelZoom<'a, Message, Renderer>
+where
+    Renderer: advanced::Renderer,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.content)]
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(std::slice::from_ref(&self.content));
+    }
+
+    fn width(&self) -> Length {
+        self.content.as_widget().width()
+    }
+
+    fn height(&self) -> Length {
+        self.content.as_widget().height()
+    }
+
+    fn layout(&self, renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        self.content.as_widget().layout(renderer, limits)
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        viewport: &Rectangle,
+    ) -> event::Status {
+        let state = tree.state.downcast_mut::<State>();
+        match &event {
+            Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                state.modifiers = *modifiers;
+            }
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) if state.modifiers.control() => {
+                let y = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } | mouse::ScrollDelta::Pixels { y, .. } => *y,
+                };
+                if let Some(position) = cursor.position_over(layout.bounds()) {
+                    if y != 0.0 {
+                        shell.publish((self.on_zoom)(y > 0.0, position));
+                    }
+                    return event::Status::Captured;
+                }
+            }
+            _ => {}
+        }
+        self.content.as_widget_mut().on_event(
+            &mut tree.children[0],
+            event,
+            layout,
+            cursor,
+            renderer,
+            clipboard,
+            shell,
+            viewport,
+        )
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Renderer::Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        self.content.as_widget().draw(
+            &tree.children[0],
+            renderer,
+            theme,
+            style,
+            layout,
+            cursor,
+            viewport,
+        );
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.content.as_widget().mouse_interaction(
+            &tree.children[0],
+            layout,
+            cursor,
+            viewport,
+            renderer,
+        )
+    }
+
+    fn operate(
+        &self,
+        tree: &mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+        operation: &mut dyn Operation<Message>,
+    ) {
+        self.content
+            .as_widget()
+            .operate(&mut tree.children[0], layout, renderer, operation);
+    }
+
+    fn overlay<'b>(
+        &'b mut self,
+        tree: &'b mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+    ) -> Option<overlay::Element<'b, Message, Renderer>> {
+        self.content
+            .as_widget_mut()
+            .overlay(&mut tree.children[0], layout, renderer)
+    }
+}
+
+impl<'a, Message, Renderer> From<WheelZoom<'a, Message, Renderer>>
+    for Element<'a, Message, Renderer>
+where
+    Renderer: 'a + advanced::Renderer,
+    Message: 'a,
+{
+    fn from(wheel_zoom: WheelZoom<'a, Message, Renderer>) -> Self {
+        Element::new(wheel_zoom)
+    }
+}
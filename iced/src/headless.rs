@@ -0,0 +1,217 @@
+//! Subcommands that run without opening a window
+
+use crate::cli::Command;
+use crate::color::ColorManagement;
+use crate::config::Config;
+use crate::gui::types::{ImageCard, ImageSource};
+use crate::info::ImageInfo;
+use crate::utils::{cache_dir, create_cache_dir, generate_thumb, read_sources, source_info};
+use indexmap::IndexSet;
+use std::io::{IsTerminal, Write};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Run `command` on a runtime with as many threads as thumbnails are made at the same time
+pub fn run(command: Command, config: Result<Config, String>) -> ExitCode {
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let jobs = config.concurrency.thumb_workers.max(1);
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(jobs)
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            eprintln!("cannot start the runtime: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match command {
+        Command::Thumbs { sources, quiet } => runtime.block_on(thumbs(sources, config, quiet)),
+        Command::Info { sources } => runtime.block_on(info(sources, jobs)),
+    }
+}
+
+/// Counts of the thumbnails done so far, printed as they change
+struct Progress {
+    total: usize,
+    generated: usize,
+    cached: usize,
+    failed: usize,
+    quiet: bool,
+    /// Rewrite a single line instead of printing one every tenth of the way
+    terminal: bool,
+}
+
+impl Progress {
+    fn new(total: usize, quiet: bool) -> Self {
+        Self {
+            total,
+            generated: 0,
+            cached: 0,
+            failed: 0,
+            quiet,
+            terminal: std::io::stderr().is_terminal(),
+        }
+    }
+
+    fn done(&self) -> usize {
+        self.generated + self.cached + self.failed
+    }
+
+    fn record(&mut self, source: &ImageSource, cached: bool, made: bool) {
+        match (made, cached) {
+            (false, _) => {
+                self.failed += 1;
+                if self.terminal && !self.quiet {
+                    eprint!("\r\x1b[K");
+                }
+                eprintln!("cannot make the thumbnail of {}", source.key());
+            }
+            (true, true) => self.cached += 1,
+            (true, false) => self.generated += 1,
+        }
+        if self.quiet {
+            return;
+        }
+        let done = self.done();
+        if self.terminal {
+            eprint!("\r{done}/{} thumbnails", self.total);
+            let _ = std::io::stderr().flush();
+        } else if done * 10 / self.total != (done - 1) * 10 / self.total {
+            eprintln!("{done}/{} thumbnails", self.total);
+        }
+    }
+
+    fn finish(&self) {
+        if self.quiet {
+            return;
+        }
+        if self.terminal {
+            eprint!("\r\x1b[K");
+        }
+        eprintln!(
+            "{} thumbnails: {} generated, {} already cached, {} failed",
+            self.total, self.generated, self.cached, self.failed
+        );
+    }
+}
+
+/// Fill the cache with the thumbnails the gallery would show for `sources`
+async fn thumbs(sources: Vec<String>, config: Config, quiet: bool) -> ExitCode {
+    // the colors of the thumbnails depend on the display profile
+    let color = match ColorManagement::new(&config.color) {
+        Ok(color) => color,
+        Err(err) => {
+            let profile = config.color.display_profile.unwrap_or_default();
+            eprintln!("cannot load display profile {}: {}", profile.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = create_cache_dir().await {
+        eprintln!("cannot create {}: {}", cache_dir().display(), err);
+        return ExitCode::FAILURE;
+    }
+    let sources = read_sources(sources.iter().map(ImageSource::new).collect()).await;
+    let width = config.layout.thumb_width;
+    let cards: IndexSet<ImageCard> = sources
+        .into_iter()
+        .map(|source| ImageCard::new(source).with_width(width))
+        .collect();
+    if cards.is_empty() {
+        eprintln!("no image found");
+        return ExitCode::FAILURE;
+    }
+
+    let cards = Arc::new(cards);
+    let next = Arc::new(AtomicUsize::new(0));
+    let progress = Arc::new(Mutex::new(Progress::new(cards.len(), quiet)));
+    let workers: Vec<_> = (0..config.concurrency.thumb_workers.max(1))
+        .map(|_| {
+            let (cards, next, progress, color) =
+                (cards.clone(), next.clone(), progress.clone(), color.clone());
+            tokio::spawn(async move {
+                while let Some(card) = cards.get_index(next.fetch_add(1, Ordering::Relaxed)) {
+                    let cached = card.thumb.exists();
+                    let made = generate_thumb(card.clone(), color.clone()).await.is_some();
+                    if let Ok(mut progress) = progress.lock() {
+                        progress.record(&card.preview, cached, made);
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.await;
+    }
+
+    let Ok(progress) = progress.lock() else {
+        return ExitCode::FAILURE;
+    };
+    progress.finish();
+    if progress.failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Print a JSON line for every image of `sources`, in the order they are given
+async fn info(sources: Vec<String>, jobs: usize) -> ExitCode {
+    // sources are expanded one by one so the files that aren't images are reported
+    let mut images = Vec::new();
+    for source in sources.iter().map(ImageSource::new) {
+        let expanded = read_sources(vec![source.clone()]).await;
+        match &source {
+            ImageSource::Path(path) if expanded.is_empty() && !path.is_dir() => {
+                let error = match path.exists() {
+                    true => format!("{} is not an image", path.display()),
+                    false => format!("{} does not exist", path.display()),
+                };
+                images.push(Err(ImageInfo::error(&source, error)));
+            }
+            _ => images.extend(expanded.into_iter().map(Ok)),
+        }
+    }
+
+    let mut failed = false;
+    let mut stdout = std::io::stdout().lock();
+    for batch in images.chunks(jobs) {
+        let tasks: Vec<_> = batch
+            .iter()
+            .cloned()
+            .map(|image| {
+                tokio::spawn(async move {
+                    match image {
+                        Ok(source) => source_info(source).await,
+                        Err(info) => info,
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            let Ok(info) = task.await else {
+                failed = true;
+                continue;
+            };
+            failed |= info.error.is_some();
+            let line = serde_json::to_string(&info).unwrap_or_default();
+            // stop quietly when the output is closed, by `head` for example
+            if writeln!(stdout, "{line}").is_err() {
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,150 @@
+//! Luminance and channel histograms of an image, for checking its exposure
+
+use image::{Rgba, RgbaImage};
+use std::fmt;
+
+const BINS: usize = 256;
+/// Share of the pixels at either end of a channel above which it's reported as clipped
+const CLIPPING_WARNING: f64 = 0.005;
+pub const GRAPH_WIDTH: u32 = BINS as u32;
+pub const GRAPH_HEIGHT: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Luminance,
+    Red,
+    Green,
+    Blue,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [Channel::Luminance, Channel::Red, Channel::Green, Channel::Blue];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Luminance => "L",
+            Channel::Red => "R",
+            Channel::Green => "G",
+            Channel::Blue => "B",
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelStats {
+    pub min: u8,
+    pub max: u8,
+    pub mean: f64,
+    /// Share of the pixels at 0
+    pub shadows: f64,
+    /// Share of the pixels at 255
+    pub highlights: f64,
+}
+
+impl fmt::Display for ChannelStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "min {:3}  max {:3}  mean {:6.2}", self.min, self.max, self.mean)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    /// Pixel counts of each value, in the order of `Channel::ALL`
+    bins: [[u64; BINS]; 4],
+    total: u64,
+}
+
+impl Histogram {
+    /// Count the values of RGBA pixels, fully transparent ones are skipped
+    pub fn new(pixels: &[u8]) -> Self {
+        let mut bins = [[0; BINS]; 4];
+        let mut total = 0;
+        for pixel in pixels.chunks_exact(4) {
+            if pixel[3] == 0 {
+                continue;
+            }
+            let luminance =
+                0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32;
+            bins[0][luminance.round() as usize] += 1;
+            for channel in 0..3 {
+                bins[channel + 1][pixel[channel] as usize] += 1;
+            }
+            total += 1;
+        }
+        Self { bins, total }
+    }
+
+    fn bins(&self, channel: Channel) -> &[u64; BINS] {
+        &self.bins[channel as usize]
+    }
+
+    pub fn stats(&self, channel: Channel) -> Option<ChannelStats> {
+        let bins = self.bins(channel);
+        let min = bins.iter().position(|&count| count > 0)?;
+        let max = bins.iter().rposition(|&count| count > 0)?;
+        let sum: u64 = bins.iter().enumerate().map(|(value, &count)| value as u64 * count).sum();
+        let total = self.total as f64;
+        Some(ChannelStats {
+            min: min as u8,
+            max: max as u8,
+            mean: sum as f64 / total,
+            shadows: bins[0] as f64 / total,
+            highlights: bins[BINS - 1] as f64 / total,
+        })
+    }
+
+    /// Warnings like `R highlights clipped 2.1%`
+    pub fn clipping(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for channel in Channel::ALL {
+            let Some(stats) = self.stats(channel) else {
+                continue;
+            };
+            for (end, share) in [("shadows", stats.shadows), ("highlights", stats.highlights)] {
+                if share > CLIPPING_WARNING {
+                    warnings.push(format!("{channel} {end} clipped {:.1}%", share * 100.0));
+                }
+            }
+        }
+        warnings
+    }
+
+    /// The channels drawn over each other, with the luminance as a white outline
+    pub fn render(&self) -> RgbaImage {
+        // spikes at the ends would flatten the rest of the graph
+        let peak = self
+            .bins
+            .iter()
+            .flat_map(|bins| &bins[1..BINS - 1])
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let height = |count: u64| {
+            let scaled = count as f64 / peak as f64 * GRAPH_HEIGHT as f64;
+            (scaled.ceil() as u32).min(GRAPH_HEIGHT)
+        };
+        let mut graph = RgbaImage::from_pixel(GRAPH_WIDTH, GRAPH_HEIGHT, Rgba([0, 0, 0, 160]));
+        for x in 0..GRAPH_WIDTH {
+            for channel in 0..3 {
+                let top = GRAPH_HEIGHT - height(self.bins[channel + 1][x as usize]);
+                for y in top..GRAPH_HEIGHT {
+                    let pixel = graph.get_pixel_mut(x, y);
+                    pixel[channel] = pixel[channel].saturating_add(160);
+                    pixel[3] = 220;
+                }
+            }
+            let top = GRAPH_HEIGHT - height(self.bins[0][x as usize]);
+            if top < GRAPH_HEIGHT {
+                graph.put_pixel(x, top, Rgba([255, 255, 255, 255]));
+            }
+        }
+        graph
+    }
+}
@@ -0,0 +1,143 @@
+//! What fuu finds out about an image without decoding its pixels, for scripts
+
+use crate::exif::{self, Highlights};
+use crate::gui::types::ImageSource;
+use crate::utils::{is_jxl, read_dimensions, thumb_path, EXIF_SEARCH_SIZE};
+use image::codecs::{
+    bmp::BmpDecoder, gif::GifDecoder, ico::IcoDecoder, jpeg::JpegDecoder, png::PngDecoder,
+    tiff::TiffDecoder, webp::WebPDecoder,
+};
+use image::{ImageDecoder, ImageFormat};
+use jxl_oxide::{JxlImage, PixelFormat};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek};
+use std::path::Path;
+
+/// One line of `fuu info`, fields that cannot be found are null
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImageInfo {
+    /// Absolute path or url of the image
+    pub source: String,
+    pub format: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Channels and bits per channel, like `rgb8` or `la16`
+    pub color_type: Option<String>,
+    pub file_size: Option<u64>,
+    pub exif: Option<Highlights>,
+    pub cache: Option<CacheStatus>,
+    pub error: Option<String>,
+}
+
+/// What the gallery already has in its cache for an image
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStatus {
+    pub thumbnail: bool,
+    /// The perceptual hash used to find duplicates
+    pub dhash: bool,
+    /// Whether the image of a url was downloaded, null for files
+    pub download: Option<bool>,
+}
+
+impl CacheStatus {
+    pub fn new(source: &ImageSource) -> Self {
+        let thumbnail = source.thumbnail();
+        Self {
+            thumbnail: thumbnail.exists(),
+            dhash: thumbnail.with_extension("dhash").exists(),
+            download: match source {
+                ImageSource::Path(_) => None,
+                ImageSource::Url(url) => Some(thumb_path(url.as_str()).exists()),
+            },
+        }
+    }
+}
+
+impl ImageInfo {
+    pub fn error(source: &ImageSource, error: String) -> Self {
+        Self {
+            source: source.key(),
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    /// Everything found in the encoded image `data` of `source`
+    pub fn new(source: &ImageSource, data: &[u8]) -> Self {
+        Self::read(source, data, Cursor::new(data), data.len() as u64)
+    }
+
+    /// Everything found in the image file at `path`, only its header is read
+    pub fn from_file(source: &ImageSource, path: &Path) -> io::Result<Self> {
+        let file_size = fs::metadata(path)?.len();
+        let mut file = File::open(path)?;
+        // EXIF data comes before the image data
+        let mut header = Vec::new();
+        (&mut file).take(EXIF_SEARCH_SIZE).read_to_end(&mut header)?;
+        file.rewind()?;
+        Ok(Self::read(source, &header, BufReader::new(file), file_size))
+    }
+
+    /// `reader` reads the whole image from its start, which is `header`
+    fn read<R: BufRead + Seek>(
+        source: &ImageSource,
+        header: &[u8],
+        mut reader: R,
+        file_size: u64,
+    ) -> Self {
+        let dimensions = read_dimensions(header, &mut reader);
+        let format = match is_jxl(header) {
+            true => Some(String::from("jxl")),
+            false => image::guess_format(header)
+                .ok()
+                .map(|format| format!("{format:?}").to_lowercase()),
+        };
+        let color_type = reader.rewind().ok().and_then(|_| color_type(header, reader));
+        Self {
+            source: source.key(),
+            format,
+            width: dimensions.as_ref().ok().map(|dim| dim.0),
+            height: dimensions.as_ref().ok().map(|dim| dim.1),
+            color_type,
+            file_size: Some(file_size),
+            exif: exif::highlights(header),
+            cache: Some(CacheStatus::new(source)),
+            error: dimensions.err().map(|err| err.to_string()),
+        }
+    }
+}
+
+/// Color type from the header of an encoded image, named like `image` names them
+fn color_type<R: BufRead + Seek>(header: &[u8], reader: R) -> Option<String> {
+    if is_jxl(header) {
+        let jxl_image = JxlImage::builder().read(reader).ok()?;
+        let channels = match jxl_image.pixel_format() {
+            PixelFormat::Gray => "l",
+            PixelFormat::Graya => "la",
+            PixelFormat::Rgb => "rgb",
+            PixelFormat::Rgba => "rgba",
+            PixelFormat::Cmyk => "cmyk",
+            PixelFormat::Cmyka => "cmyka",
+        };
+        let bits = jxl_image
+            .image_header()
+            .metadata
+            .bit_depth
+            .bits_per_sample();
+        return Some(format!("{channels}{bits}"));
+    }
+    let format = image::guess_format(header).ok()?;
+    let color_type = match format {
+        ImageFormat::Png => PngDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Jpeg => JpegDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::WebP => WebPDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Tiff => TiffDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Gif => GifDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Bmp => BmpDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Ico => IcoDecoder::new(reader).ok()?.color_type(),
+        // the other formats are rare enough to be decoded
+        _ => image::io::Reader::with_format(reader, format).decode().ok()?.color(),
+    };
+    Some(format!("{color_type:?}").to_lowercase())
+}
@@ -1,15 +1,68 @@
+// element constructors borrow their state without naming the lifetime
+#![allow(mismatched_lifetime_syntaxes)]
+
+pub mod cli;
+pub mod color;
+pub mod config;
+pub mod contact_sheet;
+pub mod convert;
+pub mod crop;
+pub mod diff;
+pub mod exif;
+pub mod geometry;
 pub mod gui;
+pub mod headless;
+pub mod histogram;
+pub mod info;
+pub mod open_with;
+pub mod rotate;
+pub mod session;
+pub mod tags;
 pub mod utils;
+pub mod xmp;
 
+use clap::Parser;
+use cli::Cli;
+use config::Config;
+use geometry::WindowGeometry;
 use gui::fuu::Fuu;
 use gui::types::ImageSource;
+use gui::Flags;
 use iced::{Application, Settings};
-use std::env;
+use std::process::ExitCode;
+
+fn load_config(cli: &Cli) -> Result<Config, String> {
+    let mut config = Config::load(cli.config.as_deref()).map_err(|err| err.to_string())?;
+    cli.apply(&mut config);
+    config.validate()?;
+    utils::init(&config).map_err(|err| format!("cannot create http client: {err}"))?;
+    Ok(config)
+}
 
-fn main() -> iced::Result {
-    let sources: Vec<ImageSource> = env::args().skip(1).map(ImageSource::new).collect();
-    Fuu::run(Settings {
+fn main() -> ExitCode {
+    let mut cli = Cli::parse();
+    if let Some(command) = cli.command.take() {
+        return headless::run(command, load_config(&cli));
+    }
+    let sources: Vec<ImageSource> = cli.sources.iter().map(ImageSource::new).collect();
+    let config = load_config(&cli);
+    let geometry = WindowGeometry::load().unwrap_or_default();
+    let result = Fuu::run(Settings {
+        window: geometry.settings(),
         exit_on_close_request: false,
-        ..Settings::with_flags(sources)
-    })
+        ..Settings::with_flags(Flags {
+            sources,
+            config,
+            geometry,
+            fullscreen: cli.fullscreen,
+            resume: cli.resume,
+        })
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
 }
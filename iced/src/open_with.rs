@@ -0,0 +1,203 @@
+//! External programs run on images, from the `[[commands]]` of the config
+
+use std::ffi::OsString;
+use std::process::ExitStatus;
+
+const FILE: &str = "{file}";
+const MARKED: &str = "{marked}";
+const DIR: &str = "{dir}";
+
+/// What the placeholders of a command stand for
+#[derive(Debug, Clone, Default)]
+pub struct Targets {
+    /// Path or url of the current image
+    pub file: Option<OsString>,
+    /// The marked images, or the current one when none is marked
+    pub marked: Vec<OsString>,
+    /// Directory of the current image, urls have none
+    pub dir: Option<OsString>,
+}
+
+/// Whether the arguments of `command` use the placeholder `name`
+fn uses(command: &[String], name: &str) -> bool {
+    command.iter().any(|argument| argument.contains(name))
+}
+
+pub fn uses_file(command: &[String]) -> bool {
+    uses(command, FILE)
+}
+
+pub fn uses_marked(command: &[String]) -> bool {
+    uses(command, MARKED)
+}
+
+/// `value` as a single word of a POSIX shell, quoted only when it needs to be
+fn quote(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "/._-+,:@%=".contains(c);
+    if !value.is_empty() && value.chars().all(plain) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// The program and arguments of `command` with the placeholders replaced. An
+/// argument that is only a placeholder is replaced as it is, and `{marked}`
+/// becomes one argument per image. Inside a longer argument, which is usually
+/// a `sh -c` script, every value is quoted for the shell and the images are
+/// separated by spaces
+pub fn expand(command: &[String], targets: &Targets) -> Result<Vec<OsString>, String> {
+    if command.is_empty() {
+        return Err(String::from("empty command"));
+    }
+    let file = || targets.file.clone().ok_or_else(|| String::from("no current image"));
+    let dir = || targets.dir.clone().ok_or_else(|| String::from("the image has no directory"));
+    let mut arguments = Vec::new();
+    for argument in command {
+        match argument.as_str() {
+            FILE => arguments.push(file()?),
+            MARKED if targets.marked.is_empty() => return Err(String::from("no image")),
+            MARKED => arguments.extend(targets.marked.iter().cloned()),
+            DIR => arguments.push(dir()?),
+            _ => {
+                // in one pass, so a file name is never expanded again
+                let mut expanded = String::new();
+                let mut rest = argument.as_str();
+                while let Some(start) = rest.find('{') {
+                    expanded.push_str(&rest[..start]);
+                    rest = &rest[start..];
+                    let (value, len) = if rest.starts_with(FILE) {
+                        (quote(&file()?.to_string_lossy()), FILE.len())
+                    } else if rest.starts_with(MARKED) {
+                        if targets.marked.is_empty() {
+                            return Err(String::from("no image"));
+                        }
+                        let marked: Vec<_> = targets
+                            .marked
+                            .iter()
+                            .map(|path| quote(&path.to_string_lossy()))
+                            .collect();
+                        (marked.join(" "), MARKED.len())
+                    } else if rest.starts_with(DIR) {
+                        (quote(&dir()?.to_string_lossy()), DIR.len())
+                    } else {
+                        (String::from("{"), 1)
+                    };
+                    expanded.push_str(&value);
+                    rest = &rest[len..];
+                }
+                expanded.push_str(rest);
+                arguments.push(OsString::from(expanded));
+            }
+        }
+    }
+    Ok(arguments)
+}
+
+/// How a program exited
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// The last line the program wrote to stderr, usually the reason it failed
+    pub fn last_error(&self) -> Option<&str> {
+        self.stderr.lines().map(str::trim).rfind(|line| !line.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(arguments: &[&str]) -> Vec<String> {
+        arguments.iter().map(|argument| argument.to_string()).collect()
+    }
+
+    fn targets(file: &str, marked: &[&str], dir: &str) -> Targets {
+        Targets {
+            file: Some(OsString::from(file)),
+            marked: marked.iter().map(OsString::from).collect(),
+            dir: Some(OsString::from(dir)),
+        }
+    }
+
+    fn expanded(arguments: &[&str], targets: &Targets) -> Vec<String> {
+        expand(&command(arguments), targets)
+            .unwrap()
+            .into_iter()
+            .map(|argument| argument.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn whole_placeholders_are_passed_as_they_are() {
+        let targets = targets("/a b/it's.jpg", &["/a b/1.jpg", "/a b/2.jpg"], "/a b");
+        assert_eq!(
+            expanded(&["gimp", "{file}", "{dir}"], &targets),
+            ["gimp", "/a b/it's.jpg", "/a b"]
+        );
+        assert_eq!(
+            expanded(&["gimp", "{marked}"], &targets),
+            ["gimp", "/a b/1.jpg", "/a b/2.jpg"]
+        );
+    }
+
+    #[test]
+    fn plain_values_are_not_quoted() {
+        let targets = targets("/photos/a-1.jpg", &["/photos/a-1.jpg"], "/photos");
+        assert_eq!(
+            expanded(&["sh", "-c", "oxipng {file} && ls {dir}"], &targets),
+            ["sh", "-c", "oxipng /photos/a-1.jpg && ls /photos"]
+        );
+    }
+
+    #[test]
+    fn values_inside_arguments_are_quoted() {
+        let targets = targets("/a b/it's $(x).jpg", &[], "/a b");
+        assert_eq!(
+            expanded(&["sh", "-c", "oxipng {file} && ls {dir}"], &targets),
+            ["sh", "-c", r"oxipng '/a b/it'\''s $(x).jpg' && ls '/a b'"]
+        );
+    }
+
+    #[test]
+    fn urls_are_quoted() {
+        let targets = Targets {
+            file: Some(OsString::from("https://example.com/a.jpg?w=1&h=2")),
+            ..Default::default()
+        };
+        assert_eq!(
+            expanded(&["sh", "-c", "curl -O {file}"], &targets),
+            ["sh", "-c", "curl -O 'https://example.com/a.jpg?w=1&h=2'"]
+        );
+    }
+
+    #[test]
+    fn marked_images_keep_their_boundaries() {
+        let targets = targets("/a.jpg", &["/a b.jpg", "/c.jpg"], "/");
+        assert_eq!(
+            expanded(&["sh", "-c", "echo {marked}"], &targets),
+            ["sh", "-c", "echo '/a b.jpg' /c.jpg"]
+        );
+    }
+
+    #[test]
+    fn values_are_not_expanded_again() {
+        let targets = targets("/{dir}.jpg", &[], "/photos");
+        assert_eq!(
+            expanded(&["echo", "file={file} {other}"], &targets),
+            ["echo", "file='/{dir}.jpg' {other}"]
+        );
+    }
+
+    #[test]
+    fn missing_values_are_errors() {
+        assert!(expand(&[], &Targets::default()).is_err());
+        assert!(expand(&command(&["gimp", "{file}"]), &Targets::default()).is_err());
+        assert!(expand(&command(&["gimp", "{marked}"]), &Targets::default()).is_err());
+        assert!(expand(&command(&["sh", "-c", "ls {dir}"]), &Targets::default()).is_err());
+        assert!(expand(&command(&["sh", "-c", "ls {marked}"]), &Targets::default()).is_err());
+    }
+}
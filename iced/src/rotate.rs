@@ -0,0 +1,70 @@
+//! Rotating and flipping image files in place, losslessly for JPEGs
+
+use crate::color::icc_profile;
+use crate::convert::png_with_metadata;
+use crate::exif::{self, Orientation};
+use crate::utils::{content_hash, decode_image, replace_file};
+use image::{ImageFormat, ImageOutputFormat};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Formats encoded again with all they can hold, PNGs get their EXIF data and
+/// ICC profile back and the others have none
+const REENCODED: [ImageFormat; 6] = [
+    ImageFormat::Png,
+    ImageFormat::Bmp,
+    ImageFormat::Tga,
+    ImageFormat::Pnm,
+    ImageFormat::Qoi,
+    ImageFormat::Farbfeld,
+];
+
+/// Rotate or flip the image at `path` by `change`. JPEGs only get a new EXIF
+/// orientation so their pixels are untouched, other formats are decoded and
+/// encoded again. Returns the content hashes of the file before and after
+pub fn transform(path: &Path, change: Orientation) -> Result<(String, String), String> {
+    let data = fs::read(path).map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+    let format = image::guess_format(&data).ok();
+    let output = match format {
+        Some(ImageFormat::Jpeg) => {
+            let tiff = exif::exif(&data);
+            let orientation = exif::orientation(&data).followed_by(change);
+            let tiff = match tiff {
+                Some(tiff) => exif::with_orientation(&tiff, orientation)
+                    .ok_or_else(|| format!("invalid EXIF data in {}", path.display()))?,
+                None => exif::orientation_only(orientation),
+            };
+            exif::jpeg_with_exif(&data, &tiff)
+                .ok_or_else(|| format!("EXIF data too large in {}", path.display()))?
+        }
+        // only the first frame would be kept
+        Some(ImageFormat::Gif) => return Err(String::from("cannot rotate GIFs")),
+        Some(format) if REENCODED.contains(&format) => {
+            // decoded images are already turned to their orientation
+            let image = change.apply(decode_image(&data).map_err(|err| err.to_string())?);
+            let mut encoded = Cursor::new(Vec::new());
+            image
+                .write_to(&mut encoded, ImageOutputFormat::from(format))
+                .map_err(|err| err.to_string())?;
+            let encoded = encoded.into_inner();
+            match format {
+                ImageFormat::Png => {
+                    let exif = exif::exif(&data)
+                        .and_then(|tiff| exif::with_orientation(&tiff, Orientation::default()));
+                    png_with_metadata(&encoded, exif, icc_profile(&data))
+                }
+                _ => encoded,
+            }
+        }
+        // TIFF and WebP would lose their metadata, and lossy WebPs become lossless
+        Some(format) if format.can_write() => {
+            let format = format!("{format:?}").to_uppercase();
+            return Err(format!("cannot rotate {format} files without losing their metadata"));
+        }
+        _ => return Err(format!("cannot rotate {}", path.display())),
+    };
+    replace_file(path, &output)
+        .map_err(|err| format!("cannot write {}: {}", path.display(), err))?;
+    Ok((content_hash(&data), content_hash(&output)))
+}
@@ -0,0 +1,67 @@
+use crate::config::state_dir;
+use crate::gui::types::ImageSource;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// What is needed to reopen the gallery where it was left, images are
+/// referenced by path or url so removed files are simply skipped
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    pub sources: Vec<String>,
+    pub selected: Option<String>,
+    pub marked: Vec<String>,
+    pub show_selections: bool,
+    pub scroll_offset: f32,
+}
+
+fn sessions_dir() -> PathBuf {
+    state_dir().join("sessions")
+}
+
+fn last_path() -> PathBuf {
+    sessions_dir().join("last.toml")
+}
+
+/// Session file of a gallery opened on a single directory
+fn directory_path(sources: &[String]) -> Option<PathBuf> {
+    let [source] = sources else {
+        return None;
+    };
+    if !Path::new(source).is_dir() {
+        return None;
+    }
+    let mut hasher = Md5::new();
+    hasher.update(source.as_bytes());
+    Some(sessions_dir().join(format!("{:x}.toml", hasher.finalize())))
+}
+
+fn load(path: &Path) -> Option<Session> {
+    let content = std::fs::read_to_string(path).ok()?;
+    toml::from_str(&content).ok()
+}
+
+impl Session {
+    pub fn load_last() -> Option<Self> {
+        load(&last_path())
+    }
+
+    /// The session saved the last time these exact sources were opened
+    pub fn load_for(sources: &[ImageSource]) -> Option<Self> {
+        let sources: Vec<String> = sources.iter().map(ImageSource::key).collect();
+        load(&directory_path(&sources)?)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let content = toml::to_string(self).map_err(std::io::Error::other)?;
+        std::fs::create_dir_all(sessions_dir())?;
+        if let Some(path) = directory_path(&self.sources) {
+            std::fs::write(path, &content)?;
+        }
+        std::fs::write(last_path(), content)
+    }
+
+    pub fn sources(&self) -> Vec<ImageSource> {
+        self.sources.iter().map(ImageSource::new).collect()
+    }
+}
@@ -0,0 +1,333 @@
+//! Free-form tags kept in a database keyed by the content hash of the
+//! images, so they follow a file when it's renamed or moved
+
+use crate::config::data_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+
+pub type Tags = BTreeSet<String>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TagDb {
+    /// Tags by md5 of the image content
+    tags: BTreeMap<String, Tags>,
+}
+
+impl TagDb {
+    pub fn path() -> PathBuf {
+        data_dir().join("tags.toml")
+    }
+
+    pub fn load() -> Result<Self> {
+        let content = match std::fs::read_to_string(Self::path()) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        toml::from_str(&content).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let content = toml::to_string(self).map_err(Error::other)?;
+        std::fs::create_dir_all(data_dir())?;
+        std::fs::write(Self::path(), content)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&Tags> {
+        self.tags.get(hash)
+    }
+
+    /// Move the tags of an image whose content changed, to the ones it may already have
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let Some(tags) = self.tags.remove(from) {
+            self.tags.entry(to.to_string()).or_default().extend(tags);
+        }
+    }
+
+    /// Apply an edit like `cat outdoor -blurry`, words starting with `-` are removed
+    pub fn edit(&mut self, hash: &str, input: &str) {
+        let tags = self.tags.entry(hash.to_string()).or_default();
+        for word in input.split_whitespace() {
+            match word.strip_prefix('-') {
+                Some(tag) => tags.remove(&tag.to_lowercase()),
+                None => tags.insert(word.to_lowercase()),
+            };
+        }
+        if tags.is_empty() {
+            self.tags.remove(hash);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Tag(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn matches(&self, tags: &Tags) -> bool {
+        match self {
+            Expr::Tag(tag) => tags.contains(tag),
+            Expr::Not(expr) => !expr.matches(tags),
+            Expr::And(left, right) => left.matches(tags) && right.matches(tags),
+            Expr::Or(left, right) => left.matches(tags) || right.matches(tags),
+        }
+    }
+}
+
+/// A tag expression like `cat AND NOT (blurry OR dark)`, terms next to
+/// each other without an operator are joined with `AND`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    text: String,
+    expr: Expr,
+}
+
+impl Query {
+    pub fn matches(&self, tags: Option<&Tags>) -> bool {
+        let empty = Tags::new();
+        self.expr.matches(tags.unwrap_or(&empty))
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let spaced = input.replace('(', " ( ").replace(')', " ) ");
+        let mut parser = Parser {
+            tokens: spaced.split_whitespace().collect(),
+            position: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected `{token}`"));
+        }
+        Ok(Self {
+            text: input.trim().to_string(),
+            expr,
+        })
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn or(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some("OR") {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            match self.peek() {
+                Some("AND") => {
+                    self.next();
+                }
+                Some("OR" | ")") | None => return Ok(expr),
+                Some(_) => (),
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> std::result::Result<Expr, String> {
+        match self.next() {
+            Some("NOT") => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some("(") => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(")") => Ok(expr),
+                    _ => Err(String::from("missing `)`")),
+                }
+            }
+            Some(token @ ("AND" | "OR" | ")")) => Err(format!("unexpected `{token}`")),
+            Some(tag) => Ok(Expr::Tag(tag.to_lowercase())),
+            None => Err(String::from("unexpected end of the query")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str) -> Expr {
+        Expr::Tag(name.to_string())
+    }
+
+    fn not(expr: Expr) -> Expr {
+        Expr::Not(Box::new(expr))
+    }
+
+    fn and(left: Expr, right: Expr) -> Expr {
+        Expr::And(Box::new(left), Box::new(right))
+    }
+
+    fn or(left: Expr, right: Expr) -> Expr {
+        Expr::Or(Box::new(left), Box::new(right))
+    }
+
+    fn parse(input: &str) -> std::result::Result<Expr, String> {
+        input.parse::<Query>().map(|query| query.expr)
+    }
+
+    fn tags(names: &[&str]) -> Tags {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn single_tag_is_lowercased() {
+        assert_eq!(parse("Cat"), Ok(tag("cat")));
+    }
+
+    #[test]
+    fn adjacent_terms_are_joined_with_and() {
+        assert_eq!(parse("cat dog"), Ok(and(tag("cat"), tag("dog"))));
+        assert_eq!(parse("cat AND dog"), parse("cat dog"));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("cat OR dog AND bird"),
+            Ok(or(tag("cat"), and(tag("dog"), tag("bird"))))
+        );
+        assert_eq!(
+            parse("cat dog OR bird"),
+            Ok(or(and(tag("cat"), tag("dog")), tag("bird")))
+        );
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        assert_eq!(parse("NOT cat dog"), Ok(and(not(tag("cat")), tag("dog"))));
+        assert_eq!(parse("NOT NOT cat"), Ok(not(not(tag("cat")))));
+    }
+
+    #[test]
+    fn parentheses_group() {
+        assert_eq!(
+            parse("cat AND NOT (blurry OR dark)"),
+            Ok(and(tag("cat"), not(or(tag("blurry"), tag("dark")))))
+        );
+        assert_eq!(parse("((cat))"), Ok(tag("cat")));
+        assert_eq!(parse("(cat)dog"), Ok(and(tag("cat"), tag("dog"))));
+    }
+
+    #[test]
+    fn lowercase_operators_are_tags() {
+        assert_eq!(
+            parse("cat or not"),
+            Ok(and(and(tag("cat"), tag("or")), tag("not")))
+        );
+    }
+
+    #[test]
+    fn malformed_queries_are_rejected() {
+        for input in [
+            "",
+            "   ",
+            "(",
+            "(cat",
+            "cat)",
+            "()",
+            "AND cat",
+            "cat OR",
+            "cat AND",
+            "NOT",
+            "cat ) dog",
+            "OR",
+        ] {
+            assert!(parse(input).is_err(), "`{input}` was accepted");
+        }
+        assert_eq!(parse("cat)"), Err(String::from("unexpected `)`")));
+        assert_eq!(parse("(cat"), Err(String::from("missing `)`")));
+        assert_eq!(
+            parse("cat OR"),
+            Err(String::from("unexpected end of the query"))
+        );
+    }
+
+    #[test]
+    fn query_matches_tags() {
+        let query: Query = "cat AND NOT (blurry OR dark)".parse().unwrap();
+        assert!(query.matches(Some(&tags(&["cat", "outdoor"]))));
+        assert!(!query.matches(Some(&tags(&["cat", "dark"]))));
+        assert!(!query.matches(Some(&tags(&["dog"]))));
+        assert!(!query.matches(None));
+
+        let untagged: Query = "NOT cat".parse().unwrap();
+        assert!(untagged.matches(None));
+    }
+
+    #[test]
+    fn query_keeps_its_text() {
+        let query: Query = "  cat  OR dog ".parse().unwrap();
+        assert_eq!(query.to_string(), "cat  OR dog");
+    }
+
+    #[test]
+    fn edit_adds_and_removes_tags() {
+        let mut db = TagDb::default();
+        db.edit("abc", "Cat outdoor");
+        assert_eq!(db.get("abc"), Some(&tags(&["cat", "outdoor"])));
+        db.edit("abc", "-outdoor -CAT");
+        assert_eq!(db.get("abc"), None);
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn rename_moves_the_tags() {
+        let mut db = TagDb::default();
+        db.edit("old", "cat outdoor");
+        db.rename("old", "new");
+        assert_eq!(db.get("old"), None);
+        assert_eq!(db.get("new"), Some(&Tags::from(["cat".into(), "outdoor".into()])));
+    }
+
+    #[test]
+    fn rename_merges_with_existing_tags() {
+        let mut db = TagDb::default();
+        db.edit("old", "cat");
+        db.edit("new", "dog");
+        db.rename("old", "new");
+        assert_eq!(db.get("new"), Some(&Tags::from(["cat".into(), "dog".into()])));
+        db.rename("missing", "new");
+        assert_eq!(db.get("new").map(Tags::len), Some(2));
+    }
+}
@@ -1,12 +1,70 @@
+use crate::color::{icc_profile, ColorManagement};
+use crate::config::{Config, ContactSheetConfig};
+use crate::contact_sheet::{Cell, ContactSheet, Pages};
+use crate::crop::CropRect;
+use crate::convert::{convert, ConvertOptions};
+use crate::exif::{self, Orientation};
+use crate::info::ImageInfo;
+use crate::open_with::CommandOutput;
+use crate::xmp::{self, Metadata};
+use crate::gui::duplicates;
 use crate::gui::types::*;
-use image::error::{ImageError, ImageResult};
+use image::error::{DecodingError, ImageError, ImageFormatHint, ImageResult};
+use image::{DynamicImage, GenericImageView, ImageBuffer};
+use jxl_oxide::JxlImage;
 use md5::{Digest, Md5};
-use std::io::BufReader;
+use once_cell::sync::OnceCell;
+use std::io::{BufRead, BufReader, Cursor, Seek};
 use std::io::Result;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs::{self, File};
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use bytes::Bytes;
+use std::time::Duration;
+
+static CACHE_DIR: OnceCell<PathBuf> = OnceCell::new();
+/// Extension of the files written before they replace another one
+pub const TEMPORARY_EXTENSION: &str = "fuu-tmp";
+/// How far into a file its EXIF data is looked for
+pub const EXIF_SEARCH_SIZE: u64 = 128 * 1024;
+static HTTP_CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+/// Width and colors the thumbnails are made with, empty for the default width in sRGB
+static THUMB_VARIANT: OnceCell<String> = OnceCell::new();
+
+/// Set up the cache location and the http client from the user config,
+/// this must be called before any image is loaded
+pub fn init(config: &Config) -> reqwest::Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.network.timeout))
+        .user_agent(&config.network.user_agent)
+        .build()?;
+    let _ = HTTP_CLIENT.set(client);
+    let _ = CACHE_DIR.set(config.cache.dir.clone());
+    let mut variant = Vec::new();
+    if config.layout.thumb_width != Config::default().layout.thumb_width {
+        variant.push(format!("w{}", config.layout.thumb_width));
+    }
+    match (&config.color.display_profile, config.color.managed) {
+        (_, false) => variant.push(String::from("unmanaged")),
+        (None, true) => (),
+        (Some(path), true) => {
+            let mut hasher = Md5::new();
+            hasher.update(std::fs::read(path).unwrap_or_default());
+            variant.push(format!("{:x}", hasher.finalize()));
+        }
+    }
+    let _ = THUMB_VARIANT.set(variant.join("#"));
+    Ok(())
+}
+
+pub fn cache_dir() -> &'static Path {
+    CACHE_DIR.get_or_init(|| Config::default().cache.dir)
+}
+
+fn http_client() -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(reqwest::Client::new)
+}
 
 fn hash<P: AsRef<Path>>(file_name: P) -> String {
     let mut hasher = Md5::new();
@@ -16,9 +74,32 @@ fn hash<P: AsRef<Path>>(file_name: P) -> String {
 }
 
 pub fn thumb_path<P: AsRef<Path>>(file_name: P) -> PathBuf {
-    let cache_dir = dirs::cache_dir().unwrap().join("fuu");
     let hashed_name = hash(file_name);
-    cache_dir.join(hashed_name)
+    cache_dir().join(hashed_name)
+}
+
+/// Where the thumbnail of `file_name` is cached, thumbnails made with another
+/// width, for another display profile or without color management are kept apart
+pub fn thumbnail_path<P: AsRef<Path>>(file_name: P) -> PathBuf {
+    match THUMB_VARIANT.get().filter(|variant| !variant.is_empty()) {
+        Some(variant) => thumb_path(format!("{}#{}", file_name.as_ref().display(), variant)),
+        None => thumb_path(file_name),
+    }
+}
+
+// jpeg xl containers need 12 bytes to be recognized, qoi only 4
+const MAGIC_LEN: usize = 16;
+
+fn is_qoi(buf: &[u8]) -> bool {
+    buf.starts_with(b"qoif")
+}
+
+pub fn is_jxl(buf: &[u8]) -> bool {
+    infer::image::is_jxl(buf)
+}
+
+fn is_image_data(buf: &[u8]) -> bool {
+    infer::is_image(buf) || is_qoi(buf)
 }
 
 async fn is_image<P: AsRef<Path>>(path: P) -> Result<bool> {
@@ -26,9 +107,15 @@ async fn is_image<P: AsRef<Path>>(path: P) -> Result<bool> {
         return Ok(false);
     }
     let mut file = File::open(path).await?;
-    let mut buff = [0; 4];
-    file.read_exact(&mut buff).await?;
-    Ok(infer::is_image(&buff))
+    let mut buff = [0; MAGIC_LEN];
+    let mut len = 0;
+    while len < MAGIC_LEN {
+        match file.read(&mut buff[len..]).await? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(is_image_data(&buff[..len]))
 }
 
 pub async fn read_dir<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
@@ -40,9 +127,16 @@ pub async fn read_dir<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
             paths.push(path)
         }
     }
+    paths.sort();
     Ok(paths)
 }
 
+/// Canonical directory of `path`, which itself may no longer exist
+pub fn canonical_parent(path: &Path) -> Option<PathBuf> {
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty());
+    parent.unwrap_or(Path::new(".")).canonicalize().ok()
+}
+
 pub async fn read_sources(sources: Vec<ImageSource>) -> Vec<ImageSource> {
     let mut output_sources = Vec::with_capacity(sources.len());
     for source in sources {
@@ -60,27 +154,190 @@ pub async fn read_sources(sources: Vec<ImageSource>) -> Vec<ImageSource> {
     output_sources
 }
 
+/// Show `dir` in the platform file manager without waiting for it
+pub fn open_in_file_manager<P: AsRef<Path>>(dir: P) {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(target_os = "windows") {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let _ = std::process::Command::new(opener).arg(dir.as_ref()).spawn();
+}
+
 pub async fn create_cache_dir() -> Result<()> {
-    let cache_dir = dirs::cache_dir().unwrap().join("fuu");
+    let cache_dir = cache_dir();
     if !cache_dir.exists() {
         fs::create_dir_all(cache_dir).await?;
     }
     Ok(())
 }
 
+/// Hex md5 of the content of a file, it identifies an image whatever its name
+/// Content hash of a file already in memory, like `read_content_hash`
+pub fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Md5::digest(data))
+}
+
+async fn read_content_hash<P: AsRef<Path>>(path: P) -> Result<String> {
+    let mut file = File::open(path).await?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer).await? {
+            0 => break,
+            n => hasher.update(&buffer[..n]),
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Write next to the file then rename over it, so it's never left half written
+pub fn replace_file(path: &Path, data: &[u8]) -> Result<()> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".");
+    name.push(TEMPORARY_EXTENSION);
+    let temporary = path.with_file_name(name);
+    let result = (|| {
+        std::fs::write(&temporary, data)?;
+        // a new file keeps the default permissions
+        if let Ok(metadata) = std::fs::metadata(path) {
+            std::fs::set_permissions(&temporary, metadata.permissions())?;
+        }
+        std::fs::rename(&temporary, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Group the images that look alike on a blocking thread, comparing every pair
+/// and reading the file sizes takes a while in large galleries
+pub async fn group_duplicates(candidates: Vec<duplicates::Candidate>, threshold: u32) -> Vec<Vec<usize>> {
+    tokio::task::spawn_blocking(move || duplicates::find(&candidates, threshold))
+        .await
+        .unwrap_or_default()
+}
+
+/// Read the ratings and labels of local images from their sidecars
+pub async fn read_sidecars(files: Vec<(usize, PathBuf)>) -> Vec<(usize, Metadata)> {
+    tokio::task::spawn_blocking(move || {
+        files
+            .into_iter()
+            .map(|(index, path)| (index, xmp::read(path)))
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Content hashes of the files of the given images, or why they couldn't be read
+pub async fn content_hashes(
+    files: Vec<(usize, PathBuf)>,
+) -> Vec<(usize, std::result::Result<String, String>)> {
+    let mut hashes = Vec::with_capacity(files.len());
+    for (index, path) in files {
+        let hash = read_content_hash(&path)
+            .await
+            .map_err(|err| format!("cannot read {}: {}", path.display(), err));
+        hashes.push((index, hash));
+    }
+    hashes
+}
+
+fn jxl_error<E>(err: E) -> ImageError
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("JPEG XL".to_string()),
+        err,
+    ))
+}
+
+fn decode_jxl(data: &[u8]) -> ImageResult<(DynamicImage, Vec<u8>)> {
+    let jxl_image = JxlImage::builder().read(Cursor::new(data)).map_err(jxl_error)?;
+    let render = jxl_image.render_frame(0).map_err(jxl_error)?;
+    let mut stream = render.stream();
+    let (width, height) = (stream.width(), stream.height());
+    let mut buf = vec![0u8; (width * height * stream.channels()) as usize];
+    stream.write_to_buffer(&mut buf);
+    let image = match stream.channels() {
+        1 => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLuma8),
+        2 => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLumaA8),
+        3 => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgb8),
+        _ => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgba8),
+    };
+    let image = image.ok_or_else(|| jxl_error("invalid frame buffer size"))?;
+    Ok((image, jxl_image.rendered_icc()))
+}
+
+/// Decode any supported image format, including the ones `image` doesn't know about,
+/// turned the way its EXIF orientation says
+pub fn decode_image(data: &[u8]) -> ImageResult<DynamicImage> {
+    if is_jxl(data) {
+        return decode_jxl(data).map(|(image, _)| image);
+    }
+    let image = image::load_from_memory(data)?;
+    Ok(exif::orientation(data).apply(image))
+}
+
+/// Decode an image and convert it from its embedded color profile for display
+pub fn decode_managed(data: &[u8], color: &ColorManagement) -> ImageResult<DynamicImage> {
+    if is_jxl(data) {
+        let (image, icc) = decode_jxl(data)?;
+        return Ok(color.apply(image, Some(&icc)));
+    }
+    let image = exif::orientation(data).apply(image::load_from_memory(data)?);
+    if !color.enabled {
+        return Ok(image);
+    }
+    Ok(color.apply(image, icc_profile(data).as_deref()))
+}
+
+/// Dimensions of an image as it's displayed, with the width and the height
+/// swapped when its orientation turns it sideways
 pub async fn image_dimensions<P: AsRef<Path>>(image_path: P) -> ImageResult<(u32, u32)> {
-    let input_file = File::open(&image_path).await.map_err(ImageError::IoError)?;
-    let reader = BufReader::new(input_file.into_std().await);
-    image::io::Reader::new(reader)
+    let mut input_file = File::open(&image_path).await.map_err(ImageError::IoError)?;
+    // EXIF data comes before the image data
+    let mut header = Vec::new();
+    (&mut input_file)
+        .take(EXIF_SEARCH_SIZE)
+        .read_to_end(&mut header)
+        .await
+        .map_err(ImageError::IoError)?;
+    input_file.rewind().await.map_err(ImageError::IoError)?;
+    read_dimensions(&header, BufReader::new(input_file.into_std().await))
+}
+
+/// Dimensions of an encoded image already in memory, like `image_dimensions`
+pub fn data_dimensions(data: &[u8]) -> ImageResult<(u32, u32)> {
+    read_dimensions(data, Cursor::new(data))
+}
+
+/// Dimensions from the header of the image in `reader`, which starts with `header`
+pub fn read_dimensions<R: BufRead + Seek>(header: &[u8], reader: R) -> ImageResult<(u32, u32)> {
+    if is_jxl(header) {
+        let jxl_image = JxlImage::builder().read(reader).map_err(jxl_error)?;
+        return Ok((jxl_image.width(), jxl_image.height()));
+    }
+    let (width, height) = image::io::Reader::new(reader)
         .with_guessed_format()?
-        .into_dimensions()
+        .into_dimensions()?;
+    if exif::orientation(header).transposes() {
+        return Ok((height, width));
+    }
+    Ok((width, height))
 }
 
 // unfortunately we cannot construct new error from reqwest::Error
 async fn fetch_url(url: url::Url) -> std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync>> {
     use std::io::{Error, ErrorKind};
-    let bytes = reqwest::get(url).await?.bytes().await?;
-    if !infer::is_image(&bytes) {
+    let bytes = http_client().get(url).send().await?.bytes().await?;
+    if !is_image_data(&bytes) {
         return Err(Box::new(Error::from(ErrorKind::InvalidData)));
     }
     Ok(bytes)
@@ -90,7 +347,7 @@ async fn fetch_file<P: AsRef<Path>>(file_path: P) -> Result<Bytes> {
     fs::read(file_path).await.map(Bytes::from)
 }
 
-pub async fn fetch_source(source: ImageSource) -> Option<Bytes> {
+pub async fn fetch_bytes(source: ImageSource) -> Option<Bytes> {
     match source {
         ImageSource::Path(path) => fetch_file(path).await.ok(),
         ImageSource::Url(url) => {
@@ -104,32 +361,263 @@ pub async fn fetch_source(source: ImageSource) -> Option<Bytes> {
     }
 }
 
-pub async fn generate_thumb(image_card: ImageCard) -> Option<(u32,u32)> {
-    if image_card.thumb.exists() {
-        return image_dimensions(image_card.thumb).await.ok()
-    }
-    let input_image = match image_card.preview {
-        ImageSource::Path(preview_path) => {
-            let input_file = File::open(&preview_path).await.ok()?;
-            let reader = BufReader::new(input_file.into_std().await);
-            image::io::Reader::new(reader)
-                .with_guessed_format().ok()?
-                .decode().ok()?
+pub async fn fetch_source(source: ImageSource, color: ColorManagement) -> Option<Preview> {
+    let data = fetch_bytes(source).await?;
+    decode_managed(&data, &color).ok().map(Preview::new)
+}
+
+/// Convert an image on the blocking pool, so long encodes don't stall the interface
+pub async fn convert_source(
+    source: ImageSource,
+    options: Arc<ConvertOptions>,
+) -> std::result::Result<PathBuf, String> {
+    let Some(data) = fetch_bytes(source.clone()).await else {
+        return Err(format!("cannot read {}", source.key()));
+    };
+    tokio::task::spawn_blocking(move || {
+        let path = match &source {
+            ImageSource::Path(path) => path.clone(),
+            ImageSource::Url(url) => PathBuf::from(url.path()),
+        };
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("image"));
+        let file = matches!(source, ImageSource::Path(_)).then_some(path.as_path());
+        convert(&data, file, &stem, &options)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+/// File name of an image, or its whole key when it has none
+fn source_name(source: &ImageSource) -> String {
+    match source {
+        ImageSource::Path(path) => path.file_name().map(|name| name.to_string_lossy().to_string()),
+        ImageSource::Url(url) => url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .map(String::from),
+    }
+    .filter(|name| !name.is_empty())
+    .unwrap_or_else(|| source.key())
+}
+
+/// Decode and scale down an image for a contact sheet on the blocking pool
+async fn contact_sheet_cell(source: ImageSource, size: u32) -> std::result::Result<Cell, String> {
+    let Some(data) = fetch_bytes(source.clone()).await else {
+        return Err(format!("cannot read {}", source.key()));
+    };
+    let name = source_name(&source);
+    tokio::task::spawn_blocking(move || Cell::new(name, &data, size))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+/// Write the contact sheet pages of `sources`, one page at a time so only the
+/// images of the current one are kept in memory. The images that cannot be read
+/// are left out and listed with the pages
+pub async fn contact_sheet(
+    sources: Vec<ImageSource>,
+    config: ContactSheetConfig,
+    workers: usize,
+) -> std::result::Result<Pages, String> {
+    let mut sheet = tokio::task::spawn_blocking(move || ContactSheet::new(config))
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut pages = Pages::default();
+    for page in sources.chunks(sheet.per_page()) {
+        let mut cells = Vec::new();
+        for batch in page.chunks(workers.max(1)) {
+            let tasks: Vec<_> = batch
+                .iter()
+                .map(|source| tokio::spawn(contact_sheet_cell(source.clone(), sheet.cell_size())))
+                .collect();
+            for (source, task) in batch.iter().zip(tasks) {
+                match task.await.map_err(|err| err.to_string()).and_then(|cell| cell) {
+                    Ok(cell) => cells.push(cell),
+                    Err(err) => {
+                        eprintln!("{err}");
+                        pages.skipped.push(source_name(source));
+                    }
+                }
+            }
+        }
+        if cells.is_empty() {
+            continue;
+        }
+        let (returned, written) = tokio::task::spawn_blocking(move || {
+            let written = sheet.write(&cells);
+            (sheet, written)
+        })
+        .await
+        .map_err(|err| err.to_string())?;
+        sheet = returned;
+        pages.written.push(written?);
+    }
+    if pages.written.is_empty() {
+        return Err(String::from("none of the images could be read"));
+    }
+    Ok(pages)
+}
+
+/// Read what `fuu info` prints about an image, urls are downloaded unless
+/// the gallery already did
+pub async fn source_info(source: ImageSource) -> ImageInfo {
+    if let ImageSource::Path(path) = &source {
+        let (info_source, path) = (source.clone(), path.clone());
+        return tokio::task::spawn_blocking(move || ImageInfo::from_file(&info_source, &path))
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|info| info.map_err(|err| format!("cannot read {}: {err}", source.key())))
+            .unwrap_or_else(|err| ImageInfo::error(&source, err));
+    }
+    let Some(data) = fetch_bytes(source.clone()).await else {
+        return ImageInfo::error(&source, format!("cannot read {}", source.key()));
+    };
+    let info_source = source.clone();
+    tokio::task::spawn_blocking(move || ImageInfo::new(&info_source, &data))
+        .await
+        .unwrap_or_else(|err| ImageInfo::error(&source, err.to_string()))
+}
+
+/// Run an external program until it exits, what it writes to stderr is kept
+pub async fn run_command(arguments: Vec<std::ffi::OsString>) -> std::result::Result<CommandOutput, String> {
+    let Some((program, arguments)) = arguments.split_first() else {
+        return Err(String::from("empty command"));
+    };
+    let output = tokio::process::Command::new(program)
+        .args(arguments)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .output()
+        .await
+        .map_err(|err| format!("cannot run {}: {}", program.to_string_lossy(), err))?;
+    Ok(CommandOutput {
+        status: output.status,
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    })
+}
+
+/// Compare two previews on the blocking pool
+pub async fn diff_previews(
+    first: Preview,
+    second: Preview,
+) -> std::result::Result<DiffPreview, String> {
+    tokio::task::spawn_blocking(move || DiffPreview::new(&first, &second))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+/// Blend the second image of a diff over the first one on the blocking pool
+pub async fn onion_skin(diff: DiffPreview, opacity: f32) -> Option<Preview> {
+    tokio::task::spawn_blocking(move || diff.onion_skin(opacity))
+        .await
+        .ok()
+}
+
+/// Compute the histogram of a preview on the blocking pool
+pub async fn histogram(preview: Preview) -> Option<HistogramPreview> {
+    tokio::task::spawn_blocking(move || HistogramPreview::new(&preview))
+        .await
+        .ok()
+}
+
+/// Crop an image into a new file of `dir`, decoding and encoding it on the blocking pool
+pub async fn write_crop(
+    source: ImageSource,
+    rect: CropRect,
+    dir: PathBuf,
+    stem: String,
+) -> std::result::Result<PathBuf, String> {
+    let data = fetch_bytes(source)
+        .await
+        .ok_or_else(|| String::from("cannot read the image"))?;
+    tokio::task::spawn_blocking(move || crate::crop::export(&data, rect, &dir, &stem))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+/// Rotate or flip an image file on the blocking pool, returns its content
+/// hashes before and after
+pub async fn transform_file(
+    path: PathBuf,
+    change: Orientation,
+) -> std::result::Result<(String, String), String> {
+    tokio::task::spawn_blocking(move || crate::rotate::transform(&path, change))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+/// Difference hash of an image, similar pictures have hashes a few bits apart
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let small = image
+        .resize_exact(9, 8, image::imageops::FilterType::Triangle)
+        .into_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// The hash cached with an existing thumbnail, computed again if it's missing
+async fn cached_dhash(image_card: &ImageCard) -> Option<u64> {
+    let dhash_path = image_card.dhash_path();
+    if let Ok(content) = fs::read_to_string(&dhash_path).await {
+        if let Ok(hash) = u64::from_str_radix(content.trim(), 16) {
+            return Some(hash);
         }
+    }
+    let thumb_data = fetch_file(&image_card.thumb).await.ok()?;
+    let hash = dhash(&decode_image(&thumb_data).ok()?);
+    let _ = fs::write(dhash_path, format!("{hash:016x}")).await;
+    Some(hash)
+}
+
+pub async fn generate_thumb(image_card: ImageCard, color: ColorManagement) -> Option<ThumbInfo> {
+    if image_card.thumb.exists() {
+        let (width, height) = image_dimensions(&image_card.thumb).await.ok()?;
+        return Some(ThumbInfo {
+            width,
+            height,
+            source_dim: image_dimensions(image_card.preview.as_path()).await.ok(),
+            dhash: cached_dhash(&image_card).await,
+        });
+    }
+    let preview_data = match &image_card.preview {
+        ImageSource::Path(preview_path) => fetch_file(preview_path).await.ok()?,
         ImageSource::Url(preview_url) => {
             let preview_path = thumb_path(preview_url.as_str());
-            let preview_data = fetch_url(preview_url).await.ok()?;
+            let preview_data = fetch_url(preview_url.clone()).await.ok()?;
             fs::write(preview_path, &preview_data).await.ok()?;
-            image::load_from_memory(&preview_data).ok()?
+            preview_data
         }
     };
-    let ratio = input_image.width() as f32 / input_image.height() as f32;
-    let new_height = (image_card.width as f32 / ratio) as u32;
-    let mut writer = File::create(image_card.thumb)
-        .await.ok()?
-        .into_std()
-        .await;
-    let thumb_image = input_image.thumbnail(image_card.width, new_height);
-    thumb_image.write_to(&mut writer, image::ImageFormat::Png).ok()?;
-    Some((image_card.width, new_height))
+    // decoding and scaling would hold up the other tasks of the runtime
+    tokio::task::spawn_blocking(move || {
+        let input_image = decode_managed(&preview_data, &color).ok()?;
+        let ratio = input_image.width() as f32 / input_image.height() as f32;
+        let new_height = (image_card.width as f32 / ratio) as u32;
+        let mut writer = std::fs::File::create(&image_card.thumb).ok()?;
+        let thumb_image = input_image.thumbnail(image_card.width, new_height);
+        thumb_image.write_to(&mut writer, image::ImageFormat::Png).ok()?;
+        let hash = dhash(&thumb_image);
+        let _ = std::fs::write(image_card.dhash_path(), format!("{hash:016x}"));
+        Some(ThumbInfo {
+            width: image_card.width,
+            height: new_height,
+            source_dim: Some(input_image.dimensions()),
+            dhash: Some(hash),
+        })
+    })
+    .await
+    .ok()
+    .flatten()
 }
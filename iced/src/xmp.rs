@@ -0,0 +1,413 @@
+//! Star ratings and color labels stored in XMP sidecars, the way darktable
+//! and Lightroom read them. Existing sidecars are edited in place so the
+//! data written by other applications is kept
+
+use crate::utils::replace_file;
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const XMP_NAMESPACE: &str = "http://ns.adobe.com/xap/1.0/";
+const RATING: &str = "xmp:Rating";
+const LABEL: &str = "xmp:Label";
+const DARKTABLE_LABELS: &str = "darktable:colorlabels";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorLabel {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+impl ColorLabel {
+    pub const ALL: [ColorLabel; 5] = [
+        ColorLabel::Red,
+        ColorLabel::Yellow,
+        ColorLabel::Green,
+        ColorLabel::Blue,
+        ColorLabel::Purple,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorLabel::Red => "Red",
+            ColorLabel::Yellow => "Yellow",
+            ColorLabel::Green => "Green",
+            ColorLabel::Blue => "Blue",
+            ColorLabel::Purple => "Purple",
+        }
+    }
+
+    pub fn rgb(&self) -> [f32; 3] {
+        match self {
+            ColorLabel::Red => [0.9, 0.2, 0.2],
+            ColorLabel::Yellow => [0.95, 0.8, 0.1],
+            ColorLabel::Green => [0.2, 0.75, 0.3],
+            ColorLabel::Blue => [0.2, 0.45, 0.95],
+            ColorLabel::Purple => [0.65, 0.3, 0.85],
+        }
+    }
+}
+
+impl fmt::Display for ColorLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ColorLabel {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        ColorLabel::ALL
+            .into_iter()
+            .find(|label| label.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown color label `{name}`"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// From 0 to 5 stars
+    pub rating: u8,
+    pub label: Option<ColorLabel>,
+}
+
+/// darktable style sidecar, `photo.jpg.xmp`
+pub fn sidecar_path<P: AsRef<Path>>(image_path: P) -> PathBuf {
+    let mut path = image_path.as_ref().as_os_str().to_owned();
+    path.push(".xmp");
+    PathBuf::from(path)
+}
+
+/// Lightroom style sidecar, `photo.xmp`
+fn short_sidecar_path<P: AsRef<Path>>(image_path: P) -> PathBuf {
+    image_path.as_ref().with_extension("xmp")
+}
+
+/// The sidecar of an image if there's one
+pub fn existing_sidecar<P: AsRef<Path>>(image_path: P) -> Option<PathBuf> {
+    [sidecar_path(&image_path), short_sidecar_path(&image_path)]
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+pub fn read<P: AsRef<Path>>(image_path: P) -> Metadata {
+    existing_sidecar(image_path)
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|content| parse(&content))
+        .unwrap_or_default()
+}
+
+pub fn parse(content: &str) -> Metadata {
+    let rating = property(content, RATING)
+        .and_then(|rating| rating.trim().parse::<i32>().ok())
+        .map_or(0, |rating| rating.clamp(0, 5) as u8);
+    let label = property(content, LABEL)
+        .and_then(|label| label.trim().parse().ok())
+        .or_else(|| darktable_label(content));
+    Metadata { rating, label }
+}
+
+/// Write the metadata to the sidecar of an image, a new one is only made when
+/// there is none. A sidecar that cannot be read or edited is left untouched
+pub fn write<P: AsRef<Path>>(image_path: P, metadata: Metadata) -> Result<()> {
+    let path = existing_sidecar(&image_path).unwrap_or_else(|| sidecar_path(&image_path));
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => update(&content, metadata).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, "no rdf:Description to write to")
+        })?,
+        Err(err) if err.kind() == ErrorKind::NotFound => template(metadata),
+        Err(err) => return Err(err),
+    };
+    replace_file(&path, content.as_bytes())
+}
+
+fn template(metadata: Metadata) -> String {
+    let label = metadata
+        .label
+        .map(|label| format!("\n    {LABEL}=\"{label}\""))
+        .unwrap_or_default();
+    format!(
+        r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="{XMP_NAMESPACE}"
+    {RATING}="{}"{label}/>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>
+"#,
+        metadata.rating
+    )
+}
+
+/// Value of `name`, written either as an attribute or as an element
+fn property<'a>(content: &'a str, name: &str) -> Option<&'a str> {
+    let attribute = format!("{name}=\"");
+    if let Some(start) = content.find(&attribute) {
+        let value = &content[start + attribute.len()..];
+        return value.split('"').next();
+    }
+    let open = format!("<{name}>");
+    let start = content.find(&open)? + open.len();
+    let end = content[start..].find('<')?;
+    Some(&content[start..start + end])
+}
+
+/// darktable also keeps labels as a list of indices
+fn darktable_label(content: &str) -> Option<ColorLabel> {
+    let start = content.find(&format!("<{DARKTABLE_LABELS}>"))?;
+    let item = &content[start..].split("<rdf:li>").nth(1)?;
+    let index: usize = item.split('<').next()?.trim().parse().ok()?;
+    ColorLabel::ALL.get(index).copied()
+}
+
+/// Edit the first `rdf:Description` of an existing sidecar
+fn update(content: &str, metadata: Metadata) -> Option<String> {
+    let rating = metadata.rating.to_string();
+    let label = metadata.label.map(|label| label.name());
+    let content = set_element(content, RATING, Some(&rating))
+        .or_else(|| set_attribute(content, RATING, Some(&rating)))?;
+    let content =
+        set_element(&content, LABEL, label).or_else(|| set_attribute(&content, LABEL, label))?;
+    // darktable's own list would bring a removed label back
+    let darktable_labels = metadata.label.map(|label| {
+        let index = ColorLabel::ALL.iter().position(|&known| known == label).unwrap_or(0);
+        format!("<rdf:Seq><rdf:li>{index}</rdf:li></rdf:Seq>")
+    });
+    Some(set_element(&content, DARKTABLE_LABELS, darktable_labels.as_deref()).unwrap_or(content))
+}
+
+/// Replace the value of an element property, `None` when there is no such element
+fn set_element(content: &str, name: &str, value: Option<&str>) -> Option<String> {
+    let open = format!("<{name}>");
+    let close = format!("</{name}>");
+    let start = content.find(&open)?;
+    let end = start + content[start..].find(&close)? + close.len();
+    let element = value
+        .map(|value| format!("{open}{value}{close}"))
+        .unwrap_or_default();
+    Some(format!("{}{}{}", &content[..start], element, &content[end..]))
+}
+
+/// Set or remove an attribute of the first `rdf:Description` tag
+fn set_attribute(content: &str, name: &str, value: Option<&str>) -> Option<String> {
+    let tag_start = content.find("<rdf:Description")?;
+    let tag_len = content[tag_start..].find('>')?;
+    let mut tag = content[tag_start..tag_start + tag_len].to_string();
+    let self_closing = tag.ends_with('/');
+    if self_closing {
+        tag.pop();
+    }
+    let attribute = format!(" {name}=\"");
+    let attribute_start = tag
+        .find(&attribute)
+        .or_else(|| tag.find(&format!("\n{}", &attribute[1..])));
+    if let Some(start) = attribute_start {
+        let value_start = start + attribute.len();
+        let end = value_start + tag[value_start..].find('"')? + 1;
+        // the whitespace before the attribute goes with it
+        let start = tag[..start].trim_end().len();
+        tag.replace_range(start..end, "");
+    }
+    if !tag.contains("xmlns:xmp=") {
+        tag += &format!("\n    xmlns:xmp=\"{XMP_NAMESPACE}\"");
+    }
+    if let Some(value) = value {
+        tag += &format!("\n    {name}=\"{value}\"");
+    }
+    if self_closing {
+        tag.push('/');
+    }
+    Some(format!(
+        "{}{}{}",
+        &content[..tag_start],
+        tag,
+        &content[tag_start + tag_len..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ATTRIBUTES: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:tiff="http://ns.adobe.com/tiff/1.0/"
+    tiff:Make="Canon"
+    xmp:Rating="2"
+    xmp:Label="Green"/>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+    const ELEMENTS: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+   <xmp:Rating>4</xmp:Rating>
+   <xmp:Label>Blue</xmp:Label>
+   <xmp:CreatorTool>Lightroom</xmp:CreatorTool>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+    const DARKTABLE: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:darktable="http://darktable.sf.net/"
+    xmp:Rating="1"
+    darktable:xmp_version="5">
+   <darktable:colorlabels>
+    <rdf:Seq>
+     <rdf:li>0</rdf:li>
+    </rdf:Seq>
+   </darktable:colorlabels>
+   <darktable:history>
+    <rdf:Seq/>
+   </darktable:history>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+    fn metadata(rating: u8, label: Option<ColorLabel>) -> Metadata {
+        Metadata { rating, label }
+    }
+
+    /// An empty directory of its own for each test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fuu-xmp-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parse_attributes() {
+        assert_eq!(parse(ATTRIBUTES), metadata(2, Some(ColorLabel::Green)));
+    }
+
+    #[test]
+    fn parse_elements() {
+        assert_eq!(parse(ELEMENTS), metadata(4, Some(ColorLabel::Blue)));
+    }
+
+    #[test]
+    fn parse_darktable_labels() {
+        assert_eq!(parse(DARKTABLE), metadata(1, Some(ColorLabel::Red)));
+    }
+
+    #[test]
+    fn parse_clamps_the_rating() {
+        let rejected = ATTRIBUTES.replace("xmp:Rating=\"2\"", "xmp:Rating=\"-1\"");
+        assert_eq!(parse(&rejected).rating, 0);
+        let high = ATTRIBUTES.replace("xmp:Rating=\"2\"", "xmp:Rating=\"9\"");
+        assert_eq!(parse(&high).rating, 5);
+    }
+
+    #[test]
+    fn template_round_trip() {
+        for expected in [metadata(0, None), metadata(5, Some(ColorLabel::Purple))] {
+            assert_eq!(parse(&template(expected)), expected);
+        }
+    }
+
+    #[test]
+    fn update_attributes() {
+        let expected = metadata(5, Some(ColorLabel::Red));
+        let updated = update(ATTRIBUTES, expected).unwrap();
+        assert_eq!(parse(&updated), expected);
+        assert!(updated.contains("tiff:Make=\"Canon\""));
+        assert!(updated.trim_end().ends_with("</x:xmpmeta>"));
+        assert_eq!(updated.matches("xmp:Rating=").count(), 1);
+
+        let cleared = update(&updated, metadata(0, None)).unwrap();
+        assert_eq!(parse(&cleared), metadata(0, None));
+        assert!(!cleared.contains(LABEL));
+        assert!(cleared.contains("tiff:Make=\"Canon\""));
+        assert!(cleared.contains("xmp:Rating=\"0\"/>"));
+    }
+
+    #[test]
+    fn update_adds_a_missing_label() {
+        let unlabeled = ATTRIBUTES.replace("\n    xmp:Label=\"Green\"", "");
+        let updated = update(&unlabeled, metadata(3, Some(ColorLabel::Yellow))).unwrap();
+        assert_eq!(parse(&updated), metadata(3, Some(ColorLabel::Yellow)));
+    }
+
+    #[test]
+    fn update_elements() {
+        let expected = metadata(1, Some(ColorLabel::Yellow));
+        let updated = update(ELEMENTS, expected).unwrap();
+        assert_eq!(parse(&updated), expected);
+        assert!(updated.contains("<xmp:Rating>1</xmp:Rating>"));
+        assert!(updated.contains("<xmp:CreatorTool>Lightroom</xmp:CreatorTool>"));
+
+        let cleared = update(&updated, metadata(0, None)).unwrap();
+        assert_eq!(parse(&cleared), metadata(0, None));
+        assert!(!cleared.contains("<xmp:Label>"));
+        assert!(!cleared.contains("xmp:Label="));
+    }
+
+    #[test]
+    fn update_darktable_labels() {
+        let relabeled = update(DARKTABLE, metadata(1, Some(ColorLabel::Blue))).unwrap();
+        assert_eq!(parse(&relabeled), metadata(1, Some(ColorLabel::Blue)));
+        assert_eq!(darktable_label(&relabeled), Some(ColorLabel::Blue));
+        assert!(relabeled.contains("darktable:xmp_version=\"5\""));
+        assert!(relabeled.contains("<darktable:history>"));
+
+        // a cleared label doesn't come back from darktable's list
+        let cleared = update(&relabeled, metadata(1, None)).unwrap();
+        assert_eq!(parse(&cleared), metadata(1, None));
+        assert!(!cleared.contains("<darktable:colorlabels>"));
+        assert!(cleared.contains("<darktable:history>"));
+    }
+
+    #[test]
+    fn update_needs_a_description() {
+        assert_eq!(update("<x:xmpmeta/>", metadata(1, None)), None);
+    }
+
+    #[test]
+    fn write_creates_a_missing_sidecar() {
+        let dir = test_dir("missing");
+        let image = dir.join("photo.jpg");
+        write(&image, metadata(3, Some(ColorLabel::Red))).unwrap();
+        assert_eq!(read(&image), metadata(3, Some(ColorLabel::Red)));
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().collect();
+        assert_eq!(files.len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_keeps_a_sidecar_without_description() {
+        let dir = test_dir("description");
+        let image = dir.join("photo.jpg");
+        let content = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><other>kept</other></x:xmpmeta>";
+        std::fs::write(sidecar_path(&image), content).unwrap();
+        assert!(write(&image, metadata(5, None)).is_err());
+        assert_eq!(std::fs::read_to_string(sidecar_path(&image)).unwrap(), content);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_keeps_a_sidecar_that_is_not_utf8() {
+        let dir = test_dir("utf8");
+        let image = dir.join("photo.jpg");
+        let content = [0xFF, 0xFE, b'<', 0x80];
+        std::fs::write(short_sidecar_path(&image), content).unwrap();
+        assert!(write(&image, metadata(5, None)).is_err());
+        assert_eq!(std::fs::read(short_sidecar_path(&image)).unwrap(), content);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}